@@ -1,11 +1,115 @@
 use std::error::Error;
+use std::path::Path;
+use std::sync::mpsc;
+
+static DEFAULT_PORT: u16 = 8000;
+
+/// The port to serve on, and the arguments which aren't ours, which are the
+/// ones of the blog.
+fn parse_args(
+    args: impl IntoIterator<Item = String>,
+) -> Result<(u16, Vec<String>), Box<dyn Error>> {
+    let mut port = DEFAULT_PORT;
+    let mut blog_args = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--port" {
+            port = args.next().ok_or("--port needs a port number")?.parse()?;
+        } else {
+            blog_args.push(arg);
+        }
+    }
+    Ok((port, blog_args))
+}
+
+/// Serve the generated site until interrupted.
+async fn serve(out_directory: &Path, port: u16) -> std::io::Result<()> {
+    let footer = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    warpy::server::run(out_directory.to_string_lossy().into_owned(), [0, 0, 0, 0], footer, Some(port), false).await
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    blog::main()?;
+    let (port, blog_args) = parse_args(std::env::args().skip(1))?;
 
-    let footer = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    // Keep regenerating the site in the background, and start serving it once
+    // it was generated the first time.
+    let (ready, out_directory) = mpsc::channel();
+    let watcher = std::thread::spawn(move || {
+        blog::watch(blog_args, move |out_directory| {
+            let _ = ready.send(out_directory.to_path_buf());
+        })
+        .map_err(|e| e.to_string())
+    });
+    let out_directory = match out_directory.recv() {
+        Ok(out_directory) => out_directory,
+        // generating the site failed before it was ready
+        Err(_) => return Err(watcher.join().unwrap().unwrap_err().into()),
+    };
 
-    warpy::server::run(format!("{}/../site", env!("CARGO_MANIFEST_DIR")), [0, 0, 0, 0], footer, Some(8000), false).await?;
+    serve(&out_directory, port).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+
+    #[test]
+    fn the_port_is_taken_out_of_the_arguments() {
+        let args = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+        let (port, blog_args) = args(&["--output-dir", "out"]).unwrap();
+        assert_eq!(port, DEFAULT_PORT);
+        assert_eq!(blog_args, ["--output-dir", "out"]);
+
+        let (port, blog_args) = args(&["--port", "8080", "--output-dir", "out"]).unwrap();
+        assert_eq!(port, 8080);
+        assert_eq!(blog_args, ["--output-dir", "out"]);
+
+        assert_eq!(args(&["--port"]).unwrap_err().to_string(), "--port needs a port number");
+        assert!(args(&["--port", "http"]).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_site_is_served() {
+        let dir = std::env::temp_dir().join(format!("serve-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "<h1>Hello</h1>").unwrap();
+        // a port nothing else listens on
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+        let server = tokio::spawn({
+            let dir = dir.clone();
+            async move { serve(&dir, port).await }
+        });
+        let response = tokio::task::spawn_blocking(move || {
+            let mut attempts = 0;
+            let mut stream = loop {
+                match TcpStream::connect(("127.0.0.1", port)) {
+                    Ok(stream) => break stream,
+                    Err(e) if attempts == 100 => panic!("the server didn't start: {}", e),
+                    Err(_) => std::thread::sleep(Duration::from_millis(50)),
+                }
+                attempts += 1;
+            };
+            stream
+                // the server logs the user agent of every request
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nUser-Agent: test\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+        .await
+        .unwrap();
+        server.abort();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("<h1>Hello</h1>"), "{}", response);
+    }
+}
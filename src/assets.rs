@@ -0,0 +1,44 @@
+//! Fingerprints of the static assets linked from posts, so their urls change
+//! whenever their content does and they can be cached for a long time.
+
+use std::path::Path;
+
+/// A short hash of the content of the file at `path`, or `None` if it can't be
+/// read. The hash only depends on the content, so it's the same on every build
+/// and with every compiler, unlike the ones of `std::hash`.
+pub(crate) fn content_hash(path: &Path) -> Option<String> {
+    let data = std::fs::read(path).ok()?;
+    Some(format!("{:08x}", fnv1a(&data) >> 32))
+}
+
+/// The 64-bit FNV-1a hash.
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::TempDir;
+    use super::*;
+
+    #[test]
+    fn hashes_only_depend_on_the_content() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        let dir = TempDir::new();
+        dir.write("a.css", "a");
+        dir.write("b.css", "a");
+        let hash = |name: &str| content_hash(&dir.path().join(name));
+        assert_eq!(hash("a.css").as_deref(), Some("af63dc4c"));
+        assert_eq!(hash("b.css"), hash("a.css"));
+        dir.write("b.css", "b");
+        assert_ne!(hash("b.css"), hash("a.css"));
+        assert_eq!(hash("missing.css"), None);
+    }
+}
@@ -4,15 +4,25 @@ use std::error::Error;
 mod lib;
 
 pub fn main() -> Result<(), Box<dyn Error>> {
-    lib::main()?;
+    if std::env::args().skip(1).any(|arg| arg == "--watch") {
+        return lib::watch(std::env::args().skip(1), |_| {});
+    }
+    if std::env::args().skip(1).any(|arg| arg == "--check") {
+        lib::check()?;
+        println!("blog has been checked");
+        return Ok(());
+    }
+
+    let out_directory = lib::main()?;
+    let out_directory = out_directory.canonicalize().unwrap_or(out_directory);
 
     println!("blog has been generated; you can now serve its content by running\n\
-              {INDENT}python3 -m http.server --directory {ROOT}/site\n\
+              {INDENT}python3 -m http.server --directory {OUT}\n\
               or running:\n\
               {INDENT}cargo run -p serve\n\
               or you can read it directly by opening a web browser on:\n\
-              {INDENT}file:///{ROOT}/site/index.html",
-             ROOT=env!("CARGO_MANIFEST_DIR"), INDENT="    ");
+              {INDENT}file:///{OUT}/index.html",
+             OUT=out_directory.display(), INDENT="    ");
 
     Ok(())
 }
@@ -1,10 +1,25 @@
-use super::posts::Post;
+use super::cache::{self, PostCache};
+use super::config::Config;
+use super::links;
+use super::posts::{self, Post, PostLink, RenderContext, SeriesPart};
+use super::slugs::slugify;
+use super::vfs::{Disk, FileSystem, Metadata};
+use chrono::NaiveDate;
+use comrak::plugins::syntect::SyntectAdapter;
+use glob::Pattern;
+use rayon::prelude::*;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use syntect::highlighting::ThemeSet;
 
-static MANIFEST_FILE: &str = "blog.yml";
+pub(crate) static MANIFEST_FILE: &str = "blog.yml";
+pub(crate) static DEFAULTS_FILE: &str = "defaults.yml";
 static POSTS_EXT: &str = "md";
+static EXCERPT_LENGTH: usize = 200;
+static FEED_LENGTH: usize = 10;
+static NOT_FOUND_HTML: &str = "The page you were looking for doesn't exist, or it has moved.";
 
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
@@ -24,56 +39,482 @@ pub(crate) struct Manifest {
     /// Raw html describing the blog to insert into the index page.
     pub(crate) index_html: String,
 
+    /// Raw html explaining the page a visitor was looking for doesn't exist,
+    /// for the `404.html` page. Only the one of the blog at the root of the
+    /// site is used.
+    #[serde(default = "default_not_found_html")]
+    pub(crate) not_found_html: String,
+
     /// If true, posts require a `team` in their metadata.
     pub(crate) requires_team: bool,
 
+    /// The team this blog belongs to. Required for blogs with `requires-team`.
+    #[serde(default)]
+    pub(crate) team: Option<String>,
+
     /// What text to use when linking to this blog in the "see also"
     /// section from other blogs.
     pub(crate) link_text: String,
+
+    /// Extension of the files to load as posts, without the leading dot.
+    #[serde(default = "default_posts_ext")]
+    pub(crate) posts_ext: String,
+
+    /// Name of the syntect theme used to highlight code blocks when building
+    /// the site. Code blocks are left for client-side highlighting if unset.
+    #[serde(default)]
+    pub(crate) highlight_theme: Option<String>,
+
+    /// Maximum length, in characters, of the description derived from the
+    /// first paragraph of posts which don't have one.
+    #[serde(default = "default_excerpt_length")]
+    pub(crate) excerpt_length: usize,
+
+    /// Number of posts on each page of the index. All posts are listed on a
+    /// single page if unset.
+    #[serde(default)]
+    pub(crate) page_size: Option<usize>,
+
+    /// strftime-style format used to display the dates of posts, e.g.
+    /// `%B %-d, %Y`. Defaults to abbreviated month names, like `Jan. 5, 2024`.
+    #[serde(default)]
+    pub(crate) date_format: Option<String>,
+
+    /// Number of levels the headings of posts are moved down by, for blogs
+    /// whose pages already have a heading of their own.
+    #[serde(default)]
+    pub(crate) heading_shift: u32,
+
+    /// Deepest level of headings listed in the table of contents of posts,
+    /// e.g. `3` for `#`, `##` and `###`. Posts can set their own with
+    /// `toc_max_depth`. All headings are listed if unset.
+    #[serde(default)]
+    pub(crate) toc_max_depth: Option<u32>,
+
+    /// Whether TeX written between `$` (inline) or `$$` (display) in posts is
+    /// wrapped in the markup KaTeX's auto-render extension looks for.
+    #[serde(default)]
+    pub(crate) math: bool,
+
+    /// Whether the local images of posts are given the `width` and `height` of
+    /// their file, and loaded lazily.
+    #[serde(default)]
+    pub(crate) image_dimensions: bool,
+
+    /// Whether links to the static assets, like images, carry a hash of their
+    /// content, so they can be cached for a long time.
+    #[serde(default)]
+    pub(crate) asset_hashes: bool,
+
+    /// Whether the blog is left out of what lists the posts of every blog,
+    /// like the sitemap and the search index, and of the "see also" links of
+    /// other blogs. Its own pages are still generated.
+    #[serde(default)]
+    pub(crate) unlisted: bool,
+
+    /// Stylesheets added to the pages of the blog after the ones of the site,
+    /// as paths relative to its directory, e.g. `styles/changelog.css`. They
+    /// are copied to the same path below the blog.
+    #[serde(default)]
+    pub(crate) extra_styles: Vec<String>,
+
+    /// Image used in the previews of posts without an `image` of their own,
+    /// like social media cards, e.g. `/images/2024-card.png`.
+    #[serde(default)]
+    pub(crate) preview_image: Option<String>,
+
+    /// Whether the blog has feeds, along with the links feed readers find them
+    /// by. Blogs without one are left out of the feed of every blog too.
+    #[serde(default = "default_generate_feed")]
+    pub(crate) generate_feed: bool,
+
+    /// Number of the most recent posts included in the feeds.
+    #[serde(default = "default_feed_length")]
+    pub(crate) feed_length: usize,
+
+    /// Whether feeds carry the whole content of posts, or only their
+    /// description.
+    #[serde(default = "default_feed_full_content")]
+    pub(crate) feed_full_content: bool,
+
+    /// Whether posts dated in the future are held back until their date.
+    #[serde(default)]
+    pub(crate) schedule_posts: bool,
+
+    /// The url of posts, relative to the blog, as a pattern of `:year`,
+    /// `:month`, `:day` and `:slug`, e.g. `:year/:slug/`.
+    #[serde(default = "default_permalink")]
+    pub(crate) permalink: String,
+
+    /// How posts are sorted: newest first, or by the `order` of their front
+    /// matter for blogs meant to be read in sequence.
+    #[serde(default)]
+    pub(crate) sort_by: SortBy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum SortBy {
+    #[default]
+    Date,
+    /// Ascending, posts without an order coming last. Posts of the same
+    /// order are sorted by date.
+    Order,
 }
 
+/// The first page is the index itself, the others are `page/<number>/`.
+fn page_path(number: usize) -> String {
+    if number == 1 {
+        "index.html".into()
+    } else {
+        format!("page/{}/index.html", number)
+    }
+}
+
+fn default_not_found_html() -> String {
+    NOT_FOUND_HTML.into()
+}
+
+fn default_permalink() -> String {
+    posts::DEFAULT_PERMALINK.into()
+}
+
+fn default_posts_ext() -> String {
+    POSTS_EXT.into()
+}
+
+fn default_excerpt_length() -> usize {
+    EXCERPT_LENGTH
+}
+
+fn default_generate_feed() -> bool {
+    true
+}
+
+fn default_feed_length() -> usize {
+    FEED_LENGTH
+}
+
+fn default_feed_full_content() -> bool {
+    true
+}
+
+/// The posts written by one author.
+pub(crate) struct AuthorPosts<'a> {
+    /// The name as written in the first post found.
+    pub(crate) name: &'a str,
+    pub(crate) posts: Vec<&'a Post>,
+}
+
+/// One page of a blog's index.
 #[derive(Serialize)]
+pub(crate) struct Page<'a> {
+    /// Starting at 1.
+    pub(crate) number: usize,
+    /// Path of the page, relative to the blog's index.
+    pub(crate) path: String,
+    pub(crate) posts: &'a [Post],
+    /// Path of the page with newer posts, if any.
+    pub(crate) prev: Option<String>,
+    /// Path of the page with older posts, if any.
+    pub(crate) next: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
 pub(crate) struct Blog {
     title: String,
     index_title: String,
     link_text: String,
     description: String,
     maintained_by: String,
+    team: Option<String>,
     index_html: String,
+    #[serde(skip)]
+    not_found_html: String,
+    extra_styles: Vec<String>,
     #[serde(serialize_with = "add_postfix_slash")]
     prefix: PathBuf,
     posts: Vec<Post>,
+    #[serde(skip)]
+    page_size: Option<usize>,
+    generate_feed: bool,
+    #[serde(skip)]
+    feed_length: usize,
+    feed_full_content: bool,
+    #[serde(skip)]
+    image_dimensions: bool,
+    #[serde(skip)]
+    asset_hashes: bool,
+    #[serde(skip)]
+    unlisted: bool,
+    #[serde(skip)]
+    sort_by: SortBy,
+    #[serde(skip)]
+    permalink: String,
+    #[serde(skip)]
+    dir: PathBuf,
+    /// The `base_url` of the config the blog was loaded with.
+    #[serde(skip)]
+    base_url: String,
 }
 
 impl Blog {
-    fn load(prefix: PathBuf, dir: &Path) -> Result<Self, Box<dyn Error>> {
-        let manifest_content = std::fs::read_to_string(dir.join(MANIFEST_FILE))?;
-        let manifest: Manifest = serde_yaml::from_str(&manifest_content)?;
+    pub(crate) fn load(
+        prefix: PathBuf,
+        dir: &Path,
+        cache: &PostCache,
+        config: &Config,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Blog::load_from(&Disk, prefix, dir, cache, config)
+    }
+
+    /// Load the blog in `dir` from the given filesystem.
+    pub(crate) fn load_from(
+        fs: &dyn FileSystem,
+        prefix: PathBuf,
+        dir: &Path,
+        cache: &PostCache,
+        config: &Config,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (manifest, manifest_source) = read_manifest(fs, &prefix, dir)?;
+        let context = RenderContext::new(load_highlighter(&manifest, dir)?, config);
+
+        let mut paths = Vec::new();
+        for path in fs.read_dir(dir)? {
+            if is_post(fs, &path, &manifest)? {
+                paths.push(path);
+            }
+        }
+        // directories list their entries in no particular order
+        paths.sort();
+
+        // Parsing and rendering the markdown is the expensive part, so skip it
+        // for unchanged posts and do it in parallel for the others. Collecting
+        // the results in order means the first error reported is the first one
+        // in directory order.
+        let results: Vec<_> = paths
+            .par_iter()
+            .map(|path| {
+                open_post(
+                    fs,
+                    path,
+                    &prefix,
+                    &manifest,
+                    &manifest_source,
+                    &context,
+                    cache,
+                )
+            })
+            .collect();
 
         let mut posts = Vec::new();
-        for entry in std::fs::read_dir(dir)? {
-            let path = entry?.path();
-            let ext = path.extension().and_then(|e| e.to_str());
-            if path.metadata()?.file_type().is_file() && ext == Some(POSTS_EXT) {
-                posts.push(Post::open(&path, &manifest)?);
+        for post in results {
+            let post = post?;
+            if include_post(&post, &manifest, config) {
+                posts.push(post);
             }
         }
 
-        posts.sort_by_key(|post| {
-            format!(
-                "{}-{:02}-{:02}-{}",
-                post.year, post.month, post.day, post.title
-            )
-        });
-        posts.reverse();
+        let mut blog = Blog {
+            title: manifest.title,
+            index_title: manifest.index_title,
+            description: manifest.description,
+            maintained_by: manifest.maintained_by,
+            team: manifest.team,
+            index_html: manifest.index_html,
+            not_found_html: manifest.not_found_html,
+            extra_styles: manifest.extra_styles,
+            link_text: manifest.link_text,
+            prefix,
+            posts,
+            page_size: manifest.page_size,
+            generate_feed: manifest.generate_feed,
+            feed_length: manifest.feed_length,
+            feed_full_content: manifest.feed_full_content,
+            image_dimensions: manifest.image_dimensions,
+            asset_hashes: manifest.asset_hashes,
+            unlisted: manifest.unlisted,
+            sort_by: manifest.sort_by,
+            permalink: manifest.permalink,
+            dir: dir.to_path_buf(),
+            base_url: config.base_url.clone(),
+        };
+        blog.arrange_posts()?;
+        Ok(blog)
+    }
 
-        // Decide which posts should show the year in the index.
-        posts[0].show_year = true;
-        for i in 1..posts.len() {
-            posts[i].show_year = posts[i - 1].year != posts[i].year;
+    /// Reload a single post of this blog from the given filesystem after its
+    /// source file changed. The post is dropped if the file was removed.
+    pub(crate) fn reload_post(
+        &mut self,
+        fs: &dyn FileSystem,
+        path: &Path,
+        cache: &PostCache,
+        config: &Config,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.posts.retain(|post| post.path != path);
+        if fs.exists(path) {
+            let (manifest, manifest_source) = read_manifest(fs, &self.prefix, &self.dir)?;
+            if is_post(fs, path, &manifest)? {
+                let context = RenderContext::new(load_highlighter(&manifest, &self.dir)?, config);
+                let post = open_post(
+                    fs,
+                    path,
+                    &self.prefix,
+                    &manifest,
+                    &manifest_source,
+                    &context,
+                    cache,
+                )?;
+                if include_post(&post, &manifest, config) {
+                    self.posts.push(post);
+                }
+            }
+        }
+        self.arrange_posts()
+    }
+
+    /// Sort the posts and compute everything that depends on their order.
+    fn arrange_posts(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut seen_urls: HashMap<&str, &Path> = HashMap::new();
+        for post in &self.posts {
+            if let Some(other) = seen_urls.insert(&post.url, &post.path) {
+                return Err(format!(
+                    "blog posts at paths `{}` and `{}` both resolve to the url `{}`",
+                    other.display(),
+                    post.path.display(),
+                    post.url
+                )
+                .into());
+            }
+        }
+
+        // Translations have to point at a post in the language they claim.
+        for post in &self.posts {
+            for (lang, url) in &post.translations {
+                let translation = match self.find_post(url) {
+                    Some(translation) => translation,
+                    None => {
+                        return Err(format!(
+                            "blog post at path `{}` has a `{}` translation at `{}`, but there is no post with that url",
+                            post.path.display(),
+                            lang,
+                            url
+                        )
+                        .into())
+                    }
+                };
+                if translation.lang != *lang {
+                    return Err(format!(
+                        "blog post at path `{}` has a `{}` translation at `{}`, but that post is in `{}`",
+                        post.path.display(),
+                        lang,
+                        url,
+                        translation.lang
+                    )
+                    .into());
+                }
+            }
+        }
+
+        let posts = &mut self.posts;
+
+        // Newest first. Posts of the same day are ordered by title, then file
+        // name, so the order doesn't depend on the order of directory entries.
+        let by_date = |a: &Post, b: &Post| {
+            (b.year, b.month, b.day)
+                .cmp(&(a.year, a.month, a.day))
+                .then_with(|| a.title.cmp(&b.title))
+                .then_with(|| a.filename.cmp(&b.filename))
+        };
+        match self.sort_by {
+            SortBy::Date => posts.sort_by(by_date),
+            SortBy::Order => posts.sort_by(|a, b| {
+                (a.order.is_none(), a.order)
+                    .cmp(&(b.order.is_none(), b.order))
+                    .then_with(|| by_date(a, b))
+            }),
+        }
+
+        // Each tag has pages named after its slug, which other tags can't share.
+        let mut tag_slugs: HashMap<String, &str> = HashMap::new();
+        for post in posts.iter() {
+            for tag in &post.tags {
+                let slug = slugify(tag);
+                let other = *tag_slugs.entry(slug.clone()).or_insert(tag);
+                if other != tag {
+                    return Err(format!(
+                        "blog post at path `{}` has the tag `{}`, but its page `tags/{}/` is the one of the tag `{}`",
+                        post.path.display(),
+                        tag,
+                        slug,
+                        other
+                    )
+                    .into());
+                }
+            }
+        }
+
+        // Decide which posts should show the year in the index. A freshly
+        // scaffolded blog may not have any posts yet.
+        if !posts.is_empty() {
+            posts[0].show_year = true;
+            for i in 1..posts.len() {
+                posts[i].show_year = posts[i - 1].year != posts[i].year;
+            }
+        }
+
+        // Number the parts of each series, oldest first.
+        let mut series: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, post) in posts.iter().enumerate() {
+            if let Some(name) = &post.series {
+                series.entry(name).or_default().push(i);
+            }
+        }
+        let mut parts = vec![None; posts.len()];
+        for members in series.values_mut() {
+            members.sort_by_key(|&i| (posts[i].year, posts[i].month, posts[i].day));
+            let link = |i: usize| PostLink {
+                title: posts[i].title.clone(),
+                url: posts[i].url.clone(),
+            };
+            for (part, &i) in members.iter().enumerate() {
+                parts[i] = Some(SeriesPart {
+                    part: part + 1,
+                    parts: members.len(),
+                    prev: part.checked_sub(1).map(|prev| link(members[prev])),
+                    next: members.get(part + 1).map(|&next| link(next)),
+                });
+            }
+        }
+        for (post, part) in posts.iter_mut().zip(parts) {
+            post.series_part = part;
+        }
+
+        // Link each post to the ones published right before and after it,
+        // whichever way the blog sorts its posts.
+        let mut newest_first: Vec<usize> = (0..posts.len()).collect();
+        newest_first.sort_by(|&a, &b| by_date(&posts[a], &posts[b]));
+        let link = |i: usize| PostLink {
+            title: posts[i].title.clone(),
+            url: posts[i].url.clone(),
+        };
+        let neighbors: Vec<_> = (0..newest_first.len())
+            .map(|k| {
+                let next = k.checked_sub(1).map(|newer| link(newest_first[newer]));
+                let prev = newest_first.get(k + 1).map(|&older| link(older));
+                (newest_first[k], prev, next)
+            })
+            .collect();
+        for (i, prev, next) in neighbors {
+            posts[i].prev = prev;
+            posts[i].next = next;
         }
 
         // Make the updated time is unique, by incrementing seconds for duplicates
+        for post in posts.iter_mut() {
+            post.set_updated(0);
+        }
         let mut last_matching_updated = 0;
         for i in 1..posts.len() {
             if posts[i].updated == posts[last_matching_updated].updated {
@@ -83,22 +524,17 @@ impl Blog {
             }
         }
 
-        Ok(Blog {
-            title: manifest.title,
-            index_title: manifest.index_title,
-            description: manifest.description,
-            maintained_by: manifest.maintained_by,
-            index_html: manifest.index_html,
-            link_text: manifest.link_text,
-            prefix,
-            posts,
-        })
+        Ok(())
     }
 
     pub(crate) fn title(&self) -> &str {
         &self.title
     }
 
+    pub(crate) fn description(&self) -> &str {
+        &self.description
+    }
+
     pub(crate) fn link_text(&self) -> &str {
         &self.link_text
     }
@@ -107,39 +543,548 @@ impl Blog {
         &self.index_title
     }
 
+    pub(crate) fn not_found_html(&self) -> &str {
+        &self.not_found_html
+    }
+
     pub(crate) fn prefix(&self) -> &Path {
         &self.prefix
     }
 
+    /// The prefix as used in urls: either empty or ending with a slash.
+    pub(crate) fn url_prefix(&self) -> String {
+        with_postfix_slash(&self.prefix)
+    }
+
+    /// Where the site is hosted, ending with a `/`.
+    pub(crate) fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The absolute url of a path relative to the blog, like the url of a post.
+    pub(crate) fn absolute_url(&self, url: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.base_url,
+            self.url_prefix(),
+            url.trim_start_matches('/')
+        )
+    }
+
     pub(crate) fn path_back_to_root(&self) -> PathBuf {
         self.prefix.components().map(|_| Path::new("../")).collect()
     }
 
+    /// Whether `file`, relative to the root of the site, is where the
+    /// permalink of a post of this blog could be, whether or not there is one.
+    pub(crate) fn is_post_file(&self, file: &str) -> bool {
+        match file.strip_prefix(&self.url_prefix()) {
+            Some(file) => posts::permalink_regex(&self.permalink).is_match(file),
+            None => false,
+        }
+    }
+
     pub(crate) fn posts(&self) -> &[Post] {
         &self.posts
     }
+
+    /// The post at `url`, relative to the blog. Leading and trailing slashes
+    /// don't matter, like the ones `add_postfix_slash` adds to prefixes.
+    pub(crate) fn find_post(&self, url: &str) -> Option<&Post> {
+        let url = url.trim_matches('/');
+        self.posts
+            .iter()
+            .find(|post| post.url.trim_matches('/') == url)
+    }
+
+    /// The date of the oldest post, or `None` if the blog has no posts yet.
+    pub(crate) fn first_post_date(&self) -> Option<NaiveDate> {
+        match self.sort_by {
+            // posts are sorted newest first
+            SortBy::Date => self.posts.last().map(post_date),
+            SortBy::Order => self.posts.iter().map(post_date).min(),
+        }
+    }
+
+    /// The date of the newest post, or `None` if the blog has no posts yet.
+    pub(crate) fn last_post_date(&self) -> Option<NaiveDate> {
+        match self.sort_by {
+            SortBy::Date => self.posts.first().map(post_date),
+            SortBy::Order => self.posts.iter().map(post_date).max(),
+        }
+    }
+
+    /// The posts pinned to the top of the index, newest first. They are part
+    /// of `posts` as well.
+    pub(crate) fn featured_posts(&self) -> Vec<&Post> {
+        self.posts.iter().filter(|post| post.featured).collect()
+    }
+
+    /// Up to `limit` other posts sharing tags with `post`, those sharing the
+    /// most first and then the newest.
+    pub(crate) fn related_posts(&self, post: &Post, limit: usize) -> Vec<&Post> {
+        let tags: HashSet<&str> = post.tags.iter().map(|tag| &**tag).collect();
+        let mut related: Vec<(usize, &Post)> = self
+            .posts
+            .iter()
+            .filter(|other| other.url != post.url)
+            .map(|other| {
+                let other_tags: HashSet<&str> = other.tags.iter().map(|tag| &**tag).collect();
+                (tags.intersection(&other_tags).count(), other)
+            })
+            .filter(|(shared, _)| *shared > 0)
+            .collect();
+        // the sort is stable, and posts are already sorted newest first
+        related.sort_by_key(|(shared, _)| std::cmp::Reverse(*shared));
+        related
+            .into_iter()
+            .take(limit)
+            .map(|(_, other)| other)
+            .collect()
+    }
+
+    /// The urls of the translations of a post by language. A translation only
+    /// has to be declared by one of the two posts, and translations of a
+    /// translation count too.
+    pub(crate) fn translations<'a>(&'a self, post: &'a Post) -> BTreeMap<&'a str, &'a str> {
+        let mut group: Vec<&Post> = vec![post];
+        let mut i = 0;
+        while i < group.len() {
+            let current = group[i];
+            for other in &self.posts {
+                let linked = current.translations.values().any(|url| *url == other.url)
+                    || other.translations.values().any(|url| *url == current.url);
+                if linked && !group.iter().any(|seen| seen.url == other.url) {
+                    group.push(other);
+                }
+            }
+            i += 1;
+        }
+        group
+            .into_iter()
+            .filter(|other| other.url != post.url)
+            .map(|other| (&*other.lang, &*other.url))
+            .collect()
+    }
+
+    /// The languages posts are written in.
+    pub(crate) fn languages(&self) -> BTreeSet<&str> {
+        self.posts.iter().map(|post| &*post.lang).collect()
+    }
+
+    pub(crate) fn posts_in_lang(&self, lang: &str) -> Vec<&Post> {
+        self.posts.iter().filter(|post| post.lang == lang).collect()
+    }
+
+    /// The posts dated after `today`.
+    pub(crate) fn future_posts(&self, today: NaiveDate) -> Vec<&Post> {
+        self.posts
+            .iter()
+            .filter(|post| NaiveDate::from_ymd(post.year, post.month, post.day) > today)
+            .collect()
+    }
+
+    pub(crate) fn page_size(&self) -> Option<usize> {
+        self.page_size
+    }
+
+    pub(crate) fn extra_styles(&self) -> &[String] {
+        &self.extra_styles
+    }
+
+    pub(crate) fn generate_feed(&self) -> bool {
+        self.generate_feed
+    }
+
+    pub(crate) fn feed_length(&self) -> usize {
+        self.feed_length
+    }
+
+    pub(crate) fn feed_full_content(&self) -> bool {
+        self.feed_full_content
+    }
+
+    pub(crate) fn image_dimensions(&self) -> bool {
+        self.image_dimensions
+    }
+
+    pub(crate) fn asset_hashes(&self) -> bool {
+        self.asset_hashes
+    }
+
+    pub(crate) fn unlisted(&self) -> bool {
+        self.unlisted
+    }
+
+    /// Split the posts into pages of `size` posts, the last one possibly
+    /// shorter. There is always at least one page, even without posts.
+    pub(crate) fn pages(&self, size: usize) -> Vec<Page<'_>> {
+        let chunks: Vec<&[Post]> = if self.posts.is_empty() {
+            vec![&[]]
+        } else {
+            self.posts.chunks(size.max(1)).collect()
+        };
+        let count = chunks.len();
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, posts)| {
+                let number = i + 1;
+                Page {
+                    number,
+                    path: page_path(number),
+                    posts,
+                    prev: (number > 1).then(|| page_path(number - 1)),
+                    next: (number < count).then(|| page_path(number + 1)),
+                }
+            })
+            .collect()
+    }
+
+    /// The directory containing this blog's manifest and posts.
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Group the posts by the year they were published in, newest first. The
+    /// groups start at the posts showing the year in the index.
+    pub(crate) fn posts_by_year(&self) -> Vec<(i32, &[Post])> {
+        let mut years = Vec::new();
+        let mut start = 0;
+        for (i, post) in self.posts.iter().enumerate().skip(1) {
+            if post.show_year {
+                years.push((self.posts[start].year, &self.posts[start..i]));
+                start = i;
+            }
+        }
+        if start < self.posts.len() {
+            years.push((self.posts[start].year, &self.posts[start..]));
+        }
+        years
+    }
+
+    /// Group the posts by author, keyed by the case-folded name. Posts with
+    /// several authors appear under each of them.
+    pub(crate) fn posts_by_author(&self) -> BTreeMap<String, AuthorPosts<'_>> {
+        let mut by_author: BTreeMap<String, AuthorPosts<'_>> = BTreeMap::new();
+        for post in &self.posts {
+            for author in &post.authors {
+                let name = author.trim();
+                by_author
+                    .entry(name.to_lowercase())
+                    .or_insert_with(|| AuthorPosts {
+                        name,
+                        posts: Vec::new(),
+                    })
+                    .posts
+                    .push(post);
+            }
+        }
+        by_author
+    }
+
+    /// Group the posts by tag. Within each tag the posts keep the order of `posts()`.
+    pub(crate) fn posts_by_tag(&self) -> BTreeMap<&str, Vec<&Post>> {
+        let mut by_tag: BTreeMap<&str, Vec<&Post>> = BTreeMap::new();
+        for post in &self.posts {
+            for tag in &post.tags {
+                by_tag.entry(tag).or_default().push(post);
+            }
+        }
+        by_tag
+    }
+}
+
+/// Read the manifest of the blog in `dir`. Fields it leaves out are taken from
+/// the `defaults.yml` files found between the base directory and `dir`, the
+/// closest one winning. The returned source includes the defaults, since they
+/// affect the posts just as much.
+fn read_manifest(
+    fs: &dyn FileSystem,
+    prefix: &Path,
+    dir: &Path,
+) -> Result<(Manifest, String), Box<dyn Error + Send + Sync>> {
+    let path = dir.join(MANIFEST_FILE);
+    let manifest_content = fs
+        .read_text(&path)
+        .map_err(|e| format!("failed to read manifest `{}`: {}", path.display(), e))?;
+
+    // walk down from the base directory, so closer defaults are applied last
+    let mut ancestors: Vec<_> = dir
+        .ancestors()
+        .take(prefix.components().count() + 1)
+        .collect();
+    ancestors.reverse();
+    let mut defaults = serde_yaml::Mapping::new();
+    let mut source = String::new();
+    for ancestor in ancestors {
+        let defaults_path = ancestor.join(DEFAULTS_FILE);
+        if !fs.metadata(&defaults_path).is_ok_and(Metadata::is_file) {
+            continue;
+        }
+        let content = fs.read_text(&defaults_path)?;
+        let values: serde_yaml::Mapping = serde_yaml::from_str(&content)
+            .map_err(|e| format!("invalid defaults `{}`: {}", defaults_path.display(), e))?;
+        defaults.extend(values);
+        source.push_str(&content);
+    }
+
+    // serde_yaml names the missing or invalid field, but not the file
+    let manifest = if defaults.is_empty() {
+        serde_yaml::from_str(&manifest_content)
+    } else {
+        serde_yaml::from_str(&manifest_content).and_then(|values: serde_yaml::Mapping| {
+            defaults.extend(values);
+            serde_yaml::from_value(serde_yaml::Value::Mapping(defaults))
+        })
+    }
+    .map_err(|e| format!("invalid manifest `{}`: {}", path.display(), e))?;
+    check_manifest(&manifest)
+        .map_err(|e| format!("invalid manifest `{}`: {}", path.display(), e))?;
+    for style in &manifest.extra_styles {
+        if !fs.metadata(&dir.join(style)).is_ok_and(Metadata::is_file) {
+            return Err(format!(
+                "manifest at path `{}` lists the stylesheet `{}`, which isn't a file in `{}`",
+                path.display(),
+                style,
+                dir.display()
+            )
+            .into());
+        }
+    }
+    source.push_str(&manifest_content);
+    Ok((manifest, source))
+}
+
+/// Check what deserializing the manifest can't.
+fn check_manifest(manifest: &Manifest) -> Result<(), String> {
+    if let Some(format) = &manifest.date_format {
+        if posts::format_date(NaiveDate::from_ymd(2000, 1, 1), format).is_err() {
+            return Err(format!("invalid date-format `{}`", format));
+        }
+    }
+    // posts of the same day can't be told apart without the slug
+    posts::permalink(&manifest.permalink, 2000, 1, 1, "slug")?;
+    if !manifest.permalink.contains(":slug") {
+        return Err(format!(
+            "permalink `{}` doesn't contain `:slug`",
+            manifest.permalink
+        ));
+    }
+    for style in &manifest.extra_styles {
+        let is_relative = Path::new(style)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !is_relative {
+            return Err(format!(
+                "extra style `{}` should be a path relative to the directory of the blog",
+                style
+            ));
+        }
+    }
+    let has_team = manifest
+        .team
+        .as_deref()
+        .is_some_and(|team| !team.trim().is_empty());
+    if manifest.requires_team && !has_team {
+        return Err("requires-team is set, but the blog doesn't name its `team`".into());
+    }
+    Ok(())
+}
+
+fn load_highlighter<'a>(
+    manifest: &'a Manifest,
+    dir: &Path,
+) -> Result<Option<SyntectAdapter<'a>>, Box<dyn Error + Send + Sync>> {
+    match &manifest.highlight_theme {
+        Some(theme) => {
+            if !ThemeSet::load_defaults().themes.contains_key(theme) {
+                return Err(format!(
+                    "manifest at path `{}` uses unknown highlight theme `{}`",
+                    dir.join(MANIFEST_FILE).display(),
+                    theme
+                )
+                .into());
+            }
+            Ok(Some(SyntectAdapter::new(theme)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Open the post at `path` of the blog at `prefix`, reusing the cached one if
+/// its sources are unchanged. The root-relative links of posts are relative to
+/// the root of their blog.
+fn open_post(
+    fs: &dyn FileSystem,
+    path: &Path,
+    prefix: &Path,
+    manifest: &Manifest,
+    manifest_source: &str,
+    context: &RenderContext,
+    cache: &PostCache,
+) -> Result<Post, Box<dyn Error + Send + Sync>> {
+    let mut source = fs.read_text(path)?;
+    source.push_str(&posts::included_sources(fs, path, &source));
+    let fingerprint = cache::fingerprint(&source, manifest_source, context.config());
+    let mut post = match cache.get(path, fingerprint) {
+        Some(post) => post,
+        None => {
+            let post = Post::open(fs, path, manifest, context)?;
+            cache.insert(path, fingerprint, &post);
+            post
+        }
+    };
+    post.contents = links::add_prefix(&post.contents, &with_postfix_slash(prefix));
+    Ok(post)
+}
+
+fn is_post(
+    fs: &dyn FileSystem,
+    path: &Path,
+    manifest: &Manifest,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let ext = path.extension().and_then(|e| e.to_str());
+    Ok(fs.metadata(path)?.is_file() && ext == Some(&*manifest.posts_ext))
+}
+
+fn post_date(post: &Post) -> NaiveDate {
+    NaiveDate::from_ymd(post.year, post.month, post.day)
+}
+
+/// Drafts, and posts scheduled for later in blogs which schedule them, are
+/// skipped unless the config asks for them, e.g. to preview them locally.
+fn include_post(post: &Post, manifest: &Manifest, config: &Config) -> bool {
+    let is_scheduled = manifest.schedule_posts
+        && NaiveDate::from_ymd(post.year, post.month, post.day) > chrono::Utc::today().naive_utc();
+    (!post.draft || config.include_drafts) && (!is_scheduled || config.preview_future)
 }
 
 /// Recursively load blogs in a directory. A blog is a directory with a `blog.yml`
 /// file inside it.
-pub(crate) fn load(base: &Path) -> Result<Vec<Blog>, Box<dyn Error>> {
+pub(crate) fn load(
+    base: &Path,
+    exclude: &[Pattern],
+    cache: &PostCache,
+    config: &Config,
+) -> Result<Vec<Blog>, Box<dyn Error>> {
+    load_from(&Disk, base, exclude, cache, config)
+}
+
+/// Recursively load the blogs in a directory of the given filesystem.
+pub(crate) fn load_from(
+    fs: &dyn FileSystem,
+    base: &Path,
+    exclude: &[Pattern],
+    cache: &PostCache,
+    config: &Config,
+) -> Result<Vec<Blog>, Box<dyn Error>> {
+    let dirs = find(fs, base, exclude)?;
+
+    let results: Vec<_> = dirs
+        .into_par_iter()
+        .map(|(prefix, dir)| Blog::load_from(fs, prefix, &dir, cache, config))
+        .collect();
+
     let mut blogs = Vec::new();
-    load_recursive(base, base, &mut blogs)?;
+    for blog in results {
+        blogs.push(blog.map_err(|e| -> Box<dyn Error> { e })?);
+    }
     Ok(blogs)
 }
 
-fn load_recursive(
+/// Collect the redirects declared by the posts of every blog, from the old
+/// path to the current path of the post. Both paths start with a `/`.
+pub(crate) fn redirects(blogs: &[Blog]) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut redirects = BTreeMap::new();
+    let mut sources: HashMap<String, &Path> = HashMap::new();
+    let urls: HashMap<String, &Path> = blogs
+        .iter()
+        .flat_map(|blog| {
+            blog.posts()
+                .iter()
+                .map(move |post| (format!("/{}{}", blog.url_prefix(), post.url), &*post.path))
+        })
+        .collect();
+
+    for blog in blogs {
+        for post in blog.posts() {
+            let target = format!("/{}{}", blog.url_prefix(), post.url);
+            for source in &post.redirects {
+                let source = format!("/{}", source.trim_start_matches('/'));
+                if let Some(other) = urls.get(&source) {
+                    return Err(format!(
+                        "blog post at path `{}` redirects from `{}`, which is the url of `{}`",
+                        post.path.display(),
+                        source,
+                        other.display()
+                    )
+                    .into());
+                }
+                if let Some(other) = sources.insert(source.clone(), &post.path) {
+                    return Err(format!(
+                        "blog posts at paths `{}` and `{}` both redirect from `{}`",
+                        other.display(),
+                        post.path.display(),
+                        source
+                    )
+                    .into());
+                }
+                redirects.insert(source, target.clone());
+            }
+        }
+    }
+    Ok(redirects)
+}
+
+/// Find the `(prefix, directory)` pair of every blog in a directory.
+/// Directories matching one of the `exclude` patterns, by name or by path
+/// relative to `base`, are skipped along with hidden directories.
+pub(crate) fn find(
+    fs: &dyn FileSystem,
+    base: &Path,
+    exclude: &[Pattern],
+) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+    let mut dirs = Vec::new();
+    let mut visited = HashSet::new();
+    find_recursive(fs, base, base, exclude, &mut dirs, &mut visited)?;
+    Ok(dirs)
+}
+
+fn is_excluded(base: &Path, dir: &Path, exclude: &[Pattern]) -> bool {
+    let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let relative = dir.strip_prefix(base).unwrap_or(dir);
+    name.starts_with('.')
+        || exclude
+            .iter()
+            .any(|pattern| pattern.matches(name) || pattern.matches_path(relative))
+}
+
+/// Collect the `(prefix, directory)` pair of every blog below `current`.
+/// Symlinked directories are followed, but each directory is only visited
+/// once so that symlink cycles don't recurse forever.
+fn find_recursive(
+    fs: &dyn FileSystem,
     base: &Path,
     current: &Path,
-    blogs: &mut Vec<Blog>,
+    exclude: &[Pattern],
+    dirs: &mut Vec<(PathBuf, PathBuf)>,
+    visited: &mut HashSet<PathBuf>,
 ) -> Result<(), Box<dyn Error>> {
-    for entry in std::fs::read_dir(current)? {
-        let path = entry?.path();
-        let file_type = path.metadata()?.file_type();
+    if !visited.insert(fs.canonicalize(current)?) {
+        return Ok(());
+    }
+    // in the same order on every build, for blogs to be listed the same way
+    let mut paths = fs.read_dir(current)?;
+    paths.sort();
+    for path in paths {
+        let metadata = fs.metadata(&path)?;
 
-        if file_type.is_dir() {
-            load_recursive(base, &path, blogs)?;
-        } else if file_type.is_file() {
+        if metadata.is_dir() {
+            if !is_excluded(base, &path, exclude) {
+                find_recursive(fs, base, &path, exclude, dirs, visited)?;
+            }
+        } else if metadata.is_file() {
             let file_name = path.file_name().and_then(|n| n.to_str());
             if let (Some(file_name), Some(parent)) = (file_name, path.parent()) {
                 if file_name == MANIFEST_FILE {
@@ -147,7 +1092,7 @@ fn load_recursive(
                         .strip_prefix(base)
                         .map(|p| p.to_path_buf())
                         .unwrap_or_else(|_| PathBuf::new());
-                    blogs.push(Blog::load(prefix, parent)?);
+                    dirs.push((prefix, parent.to_path_buf()));
                 }
             }
         }
@@ -155,13 +1100,1440 @@ fn load_recursive(
     Ok(())
 }
 
-fn add_postfix_slash<S>(path: &PathBuf, serializer: S) -> Result<S::Ok, S::Error>
+fn add_postfix_slash<S>(path: &Path, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
+    serializer.serialize_str(&with_postfix_slash(path))
+}
+
+fn with_postfix_slash(path: &Path) -> String {
     let mut str_repr = path.to_string_lossy().to_string();
     if !str_repr.is_empty() {
         str_repr.push('/');
     }
-    serializer.serialize_str(&str_repr)
+    str_repr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{self, load_blog, load_blog_with, post, TempDir};
+    use super::super::vfs::{Disk, MemoryFs};
+    use super::*;
+
+    #[test]
+    fn loads_a_blog_from_memory() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-first.md", &*post("First", "", "Hello")),
+            ("posts/2020-02-01-second.md", &*post("Second", "", "World")),
+            ("posts/notes.txt", "not a post"),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        assert_eq!(blog.title(), "Test Blog");
+        assert_eq!(blog.dir(), Path::new("posts"));
+        let posts: Vec<_> = blog.posts().iter().map(|post| &*post.url).collect();
+        assert_eq!(posts, ["2020/02/01/second.html", "2020/01/01/first.html"]);
+        assert_eq!(blog.posts()[1].path, Path::new("posts/2020-01-01-first.md"));
+    }
+
+    #[test]
+    fn finds_every_blog_from_memory() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-root.md", &*post("Root", "", "")),
+            ("posts/inside-rust/blog.yml", testing::MANIFEST),
+            (
+                "posts/inside-rust/2020-01-01-inside.md",
+                &*post("Inside", "", ""),
+            ),
+        ]);
+        let blogs = load_from(
+            &fs,
+            Path::new("posts"),
+            &[],
+            &PostCache::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let prefixes: Vec<_> = blogs.iter().map(|blog| blog.url_prefix()).collect();
+        assert_eq!(prefixes, ["", "inside-rust/"]);
+        assert_eq!(blogs[1].posts()[0].title, "Inside");
+    }
+
+    #[test]
+    fn reports_a_missing_manifest() {
+        let fs = MemoryFs::new([("posts/2020-01-01-post.md", &*post("Post", "", ""))]);
+        let err = load_blog(&fs).unwrap_err().to_string();
+        assert!(
+            err.starts_with("failed to read manifest `posts/blog.yml`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn absolute_urls_are_below_the_base_url() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-root.md", &*post("Root", "", "")),
+            ("posts/inside-rust/blog.yml", testing::MANIFEST),
+            (
+                "posts/inside-rust/2020-01-01-inside.md",
+                &*post("Inside", "", ""),
+            ),
+        ]);
+        let config = Config {
+            base_url: "https://example.com/staging/".into(),
+            ..Config::default()
+        };
+        let cache = PostCache::default();
+        let blogs = load_from(&fs, Path::new("posts"), &[], &cache, &config).unwrap();
+        let (root, inside) = (&blogs[0], &blogs[1]);
+        assert_eq!(root.absolute_url(""), "https://example.com/staging/");
+        assert_eq!(
+            root.absolute_url(&root.posts()[0].url),
+            "https://example.com/staging/2020/01/01/root.html"
+        );
+        assert_eq!(
+            inside.absolute_url(""),
+            "https://example.com/staging/inside-rust/"
+        );
+        assert_eq!(
+            inside.absolute_url(&format!("/{}", inside.posts()[0].url)),
+            "https://example.com/staging/inside-rust/2020/01/01/inside.html"
+        );
+    }
+
+    #[test]
+    fn rejects_date_formats_which_cant_display_a_date() {
+        for format in ["%H:%M", "%Z", "%Q"] {
+            let manifest = testing::manifest(&format!("date-format: \"{}\"\n", format));
+            let fs = MemoryFs::new([("posts/blog.yml", &*manifest)]);
+            let err = load_blog(&fs).unwrap_err().to_string();
+            assert_eq!(
+                err,
+                format!(
+                    "invalid manifest `posts/blog.yml`: invalid date-format `{}`",
+                    format
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn tags_cant_share_a_page() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-c.md", &*post("C", "tags: [C]\n", "")),
+            (
+                "posts/2020-02-01-cpp.md",
+                &*post("C++", "tags: [C++]\n", ""),
+            ),
+        ]);
+        let err = load_blog(&fs).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "blog post at path `posts/2020-01-01-c.md` has the tag `C`, but its page `tags/c/` is the one of the tag `C++`"
+        );
+    }
+
+    #[test]
+    fn future_posts_are_the_ones_dated_after_today() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-past.md", &post("Past", "", "")),
+            ("posts/2020-06-01-today.md", &post("Today", "", "")),
+            ("posts/2020-06-02-future.md", &post("Future", "", "")),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let future: Vec<_> = blog
+            .future_posts(NaiveDate::from_ymd(2020, 6, 1))
+            .into_iter()
+            .map(|post| post.path.clone())
+            .collect();
+        assert_eq!(future, [Path::new("posts/2020-06-02-future.md")]);
+    }
+
+    #[test]
+    fn root_relative_links_are_below_the_prefix_of_the_blog() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/inside-rust/blog.yml", testing::MANIFEST),
+            (
+                "posts/inside-rust/2020-01-01-first.md",
+                &post("First", "", "![x](/images/x.png)"),
+            ),
+        ]);
+        let blogs = load_from(
+            &fs,
+            Path::new("posts"),
+            &[],
+            &PostCache::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let blog = blogs
+            .iter()
+            .find(|blog| blog.prefix() == Path::new("inside-rust"))
+            .unwrap();
+        assert_eq!(
+            blog.posts()[0].contents,
+            "<p><img src=\"/inside-rust/images/x.png\" alt=\"x\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn unchanged_posts_are_taken_from_the_cache() {
+        let mut fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-first.md", &post("First", "", "Hello")),
+            ("posts/2020-02-01-second.md", &post("Second", "", "Hello")),
+        ]);
+        let dir = TempDir::new();
+        let cache_file = dir.path().join("posts.json");
+        let load = |fs: &MemoryFs| {
+            let cache = PostCache::load(&cache_file);
+            let blog = Blog::load_from(
+                fs,
+                PathBuf::new(),
+                Path::new("posts"),
+                &cache,
+                &Config::default(),
+            )
+            .unwrap();
+            cache.save(&cache_file).unwrap();
+            (blog, cache.parsed())
+        };
+
+        assert_eq!(load(&fs).1, 2);
+        let (blog, parsed) = load(&fs);
+        assert_eq!(parsed, 0);
+        assert_eq!(blog.posts().len(), 2);
+        assert_eq!(
+            blog.posts()[0].path,
+            Path::new("posts/2020-02-01-second.md")
+        );
+
+        fs.insert("posts/2020-01-01-first.md", post("First", "", "Hi"));
+        assert_eq!(load(&fs).1, 1);
+        // every post depends on the manifest
+        fs.insert("posts/blog.yml", testing::manifest("page-size: 10\n"));
+        assert_eq!(load(&fs).1, 2);
+
+        // and caches of other versions are thrown away
+        let saved = std::fs::read_to_string(&cache_file).unwrap();
+        let version = format!("\"version\":\"{}-", env!("CARGO_PKG_VERSION"));
+        assert!(saved.contains(&version));
+        std::fs::write(&cache_file, saved.replace(&version, "\"version\":\"0.0.0-")).unwrap();
+        assert_eq!(load(&fs).1, 2);
+    }
+
+    /// A blog of `count` posts with highlighted code blocks, for the
+    /// rendering to take a while.
+    fn many_posts(count: usize) -> MemoryFs {
+        let mut fs = MemoryFs::new([(
+            "posts/blog.yml",
+            &*testing::manifest("highlight-theme: InspiredGitHub\n"),
+        )]);
+        let body = "Some *text* with [a link](https://www.rust-lang.org/).\n\n\
+                    ```rust\nfn main() {\n    println!(\"Hello, world!\");\n}\n```\n"
+            .repeat(4);
+        for i in 0..count {
+            fs.insert(
+                format!("posts/2020-01-{:02}-post-{}.md", i % 28 + 1, i),
+                post(&format!("Post {}", i), "", &body),
+            );
+        }
+        fs
+    }
+
+    /// Run `f` with rayon limited to a single thread, like a serial build.
+    fn serially<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap()
+            .install(f)
+    }
+
+    #[test]
+    fn parallel_loads_render_like_serial_ones() {
+        let fs = many_posts(20);
+        let parallel = serde_json::to_string(&load_blog(&fs).unwrap()).unwrap();
+        let serial = serde_json::to_string(&serially(|| load_blog(&fs).unwrap())).unwrap();
+        assert!(parallel == serial);
+    }
+
+    #[test]
+    fn the_first_error_is_the_one_of_the_first_bad_post() {
+        let mut fs = many_posts(20);
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        fs.insert("posts/2020-01-03-post-30.md", "no front matter");
+        fs.insert("posts/2020-01-20-post-19.md", "no front matter");
+        for _ in 0..3 {
+            let err = load_blog(&fs).unwrap_err().to_string();
+            assert!(
+                err.starts_with("blog post at path `posts/2020-01-03-post-30.md`"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    /// How long `f` takes, at best out of a few runs.
+    fn time(mut f: impl FnMut()) -> std::time::Duration {
+        (0..3)
+            .map(|_| {
+                let start = std::time::Instant::now();
+                f();
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn loading_posts_in_parallel_is_faster() {
+        let fs = many_posts(500);
+        let serial = time(|| drop(serially(|| load_blog(&fs).unwrap())));
+        let parallel = time(|| drop(load_blog(&fs).unwrap()));
+        println!(
+            "500 posts: {:?} serially, {:?} in parallel",
+            serial, parallel
+        );
+        if rayon::current_num_threads() > 1 {
+            assert!(parallel < serial);
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn sharing_the_render_context_is_faster() {
+        let fs = many_posts(100);
+        let manifest =
+            testing::parse_manifest(&testing::manifest("highlight-theme: InspiredGitHub\n"));
+        let config = Config::default();
+        let paths = fs.read_dir(Path::new("posts")).unwrap();
+        let paths: Vec<_> = paths
+            .iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+            .collect();
+
+        let per_post = time(|| {
+            for path in &paths {
+                let highlighter = load_highlighter(&manifest, Path::new("posts")).unwrap();
+                let context = RenderContext::new(highlighter, &config);
+                Post::open(&fs, path, &manifest, &context).unwrap();
+            }
+        });
+        let shared = time(|| {
+            let highlighter = load_highlighter(&manifest, Path::new("posts")).unwrap();
+            let context = RenderContext::new(highlighter, &config);
+            for path in &paths {
+                Post::open(&fs, path, &manifest, &context).unwrap();
+            }
+        });
+        println!(
+            "100 posts: {:?} with a context each, {:?} with a shared one",
+            per_post, shared
+        );
+        assert!(shared < per_post);
+    }
+
+    #[test]
+    fn posts_cant_share_a_url() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-a.md", &*post("A", "slug: b\n", "")),
+            ("posts/2020-01-01-b.md", &*post("B", "", "")),
+        ]);
+        assert_eq!(
+            load_blog(&fs).unwrap_err().to_string(),
+            "blog posts at paths `posts/2020-01-01-a.md` and `posts/2020-01-01-b.md` both \
+             resolve to the url `2020/01/01/b.html`"
+        );
+    }
+
+    #[test]
+    fn manifest_errors_name_the_file_and_the_field() {
+        let manifest = testing::MANIFEST.replace("maintained-by: the test team\n", "");
+        let fs = MemoryFs::new([("posts/blog.yml", &*manifest)]);
+        assert_eq!(
+            load_blog(&fs).unwrap_err().to_string(),
+            "invalid manifest `posts/blog.yml`: missing field `maintained-by` at line 1 column 6"
+        );
+    }
+
+    #[test]
+    fn blogs_can_have_no_posts_yet() {
+        let fs = MemoryFs::new([("posts/blog.yml", testing::MANIFEST)]);
+        let blog = load_blog(&fs).unwrap();
+        assert!(blog.posts().is_empty());
+        assert_eq!(blog.first_post_date(), None);
+
+        // the year is shown on the first post of each year
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2019-01-01-first.md", &*post("First", "", "")),
+            ("posts/2020-01-01-second.md", &*post("Second", "", "")),
+            ("posts/2020-02-01-third.md", &*post("Third", "", "")),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let shown: Vec<_> = blog.posts().iter().map(|post| post.show_year).collect();
+        assert_eq!(shown, [true, false, true]);
+    }
+
+    #[test]
+    fn blogs_can_use_another_post_extension() {
+        let files = [
+            ("posts/2020-01-01-first.markdown", &*post("First", "", "")),
+            ("posts/2020-02-01-second.md", &*post("Second", "", "")),
+        ];
+        let mut fs = MemoryFs::new(files);
+        fs.insert("posts/blog.yml", testing::manifest("posts-ext: markdown\n"));
+        let blog = load_blog(&fs).unwrap();
+        let posts: Vec<_> = blog.posts().iter().map(|post| &*post.url).collect();
+        assert_eq!(posts, ["2020/01/01/first.html"]);
+
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        let blog = load_blog(&fs).unwrap();
+        let posts: Vec<_> = blog.posts().iter().map(|post| &*post.url).collect();
+        assert_eq!(posts, ["2020/02/01/second.html"]);
+    }
+
+    #[test]
+    fn drafts_are_only_included_on_demand() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-first.md", &*post("First", "", "")),
+            (
+                "posts/2021-01-01-draft.md",
+                &*post("Draft", "published: false\n", ""),
+            ),
+        ]);
+        let titles = |blog: &Blog| -> Vec<String> {
+            blog.posts().iter().map(|post| post.title.clone()).collect()
+        };
+        let blog = load_blog(&fs).unwrap();
+        assert_eq!(titles(&blog), ["First"]);
+        // the year of the first post is still shown, though it's not the
+        // first of the directory anymore
+        assert!(blog.posts()[0].show_year);
+
+        let config = Config {
+            include_drafts: true,
+            ..Config::default()
+        };
+        let blog = load_blog_with(&fs, &config).unwrap();
+        assert_eq!(titles(&blog), ["Draft", "First"]);
+        assert!(blog.posts()[0].draft);
+    }
+
+    #[test]
+    fn posts_are_grouped_by_tag_in_order() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-first.md",
+                &*post("First", "tags: [Cargo, Compiler]\n", ""),
+            ),
+            (
+                "posts/2020-02-01-second.md",
+                &*post("Second", "tags: [Compiler]\n", ""),
+            ),
+            ("posts/2020-03-01-third.md", &*post("Third", "", "")),
+            (
+                "posts/2020-04-01-fourth.md",
+                &*post("Fourth", "tags: [Cargo]\n", ""),
+            ),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let by_tag: Vec<(&str, Vec<&str>)> = blog
+            .posts_by_tag()
+            .into_iter()
+            .map(|(tag, posts)| (tag, posts.iter().map(|post| &*post.title).collect()))
+            .collect();
+        assert_eq!(
+            by_tag,
+            [
+                ("Cargo", vec!["Fourth", "First"]),
+                ("Compiler", vec!["Second", "First"]),
+            ]
+        );
+        let third = blog.find_post("2020/03/01/third.html").unwrap();
+        assert!(third.tags.is_empty());
+    }
+
+    #[test]
+    fn code_blocks_can_be_highlighted() {
+        let body = "```rust\nfn main() {}\n```\n\n```klingon\nqapla'\n```\n";
+        let mut fs = MemoryFs::new([("posts/2020-01-01-first.md", &*post("First", "", body))]);
+        fs.insert(
+            "posts/blog.yml",
+            testing::manifest("highlight-theme: InspiredGitHub\n"),
+        );
+        let blog = load_blog(&fs).unwrap();
+        let contents = &blog.posts()[0].contents;
+        assert!(
+            contents.contains(r#"<span style="font-weight:bold;color:#a71d5d;">fn </span>"#),
+            "{}",
+            contents
+        );
+        // unknown languages are plain text
+        assert!(
+            contents.contains(r#"<span style="color:#323232;">qapla&#39;"#),
+            "{}",
+            contents
+        );
+
+        // and without a theme, highlighting is left to the browser
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        let blog = load_blog(&fs).unwrap();
+        assert!(blog.posts()[0]
+            .contents
+            .starts_with("<pre><code class=\"language-rust\">fn main() {}\n</code></pre>"));
+
+        fs.insert(
+            "posts/blog.yml",
+            testing::manifest("highlight-theme: Neon\n"),
+        );
+        assert_eq!(
+            load_blog(&fs).unwrap_err().to_string(),
+            "manifest at path `posts/blog.yml` uses unknown highlight theme `Neon`"
+        );
+    }
+
+    #[test]
+    fn slugs_replace_the_file_name_in_urls() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-renamed.md",
+                &*post("A", "slug: legacy-name\n", ""),
+            ),
+            (
+                "posts/2020-01-01-other.md",
+                &*post("B", "slug: other-name\n", ""),
+            ),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let mut urls: Vec<_> = blog.posts().iter().map(|post| &*post.url).collect();
+        urls.sort();
+        assert_eq!(
+            urls,
+            ["2020/01/01/legacy-name.html", "2020/01/01/other-name.html"]
+        );
+
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-renamed.md",
+                &*post("A", "slug: same\n", ""),
+            ),
+            ("posts/2020-01-01-other.md", &*post("B", "slug: same\n", "")),
+        ]);
+        assert_eq!(
+            load_blog(&fs).unwrap_err().to_string(),
+            "blog posts at paths `posts/2020-01-01-other.md` and `posts/2020-01-01-renamed.md` \
+             both resolve to the url `2020/01/01/same.html`"
+        );
+    }
+
+    #[test]
+    fn posts_are_grouped_by_year_newest_first() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2018-05-01-a.md", &*post("A", "", "")),
+            ("posts/2019-01-01-b.md", &*post("B", "", "")),
+            ("posts/2019-06-01-c.md", &*post("C", "", "")),
+            ("posts/2020-02-01-d.md", &*post("D", "", "")),
+            ("posts/2020-03-01-e.md", &*post("E", "", "")),
+            ("posts/2020-04-01-f.md", &*post("F", "", "")),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let years: Vec<(i32, Vec<&str>)> = blog
+            .posts_by_year()
+            .into_iter()
+            .map(|(year, posts)| (year, posts.iter().map(|post| &*post.title).collect()))
+            .collect();
+        assert_eq!(
+            years,
+            [
+                (2020, vec!["F", "E", "D"]),
+                (2019, vec!["C", "B"]),
+                (2018, vec!["A"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn posts_are_grouped_by_each_of_their_authors() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-a.md",
+                &*post("A", "authors: [Ferris, Bors]\n", ""),
+            ),
+            (
+                "posts/2020-02-01-b.md",
+                &*post("B", "authors: \" ferris\"\n", ""),
+            ),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let by_author: Vec<(String, &str, Vec<&str>)> = blog
+            .posts_by_author()
+            .into_iter()
+            .map(|(key, author)| {
+                let posts = author.posts.iter().map(|post| &*post.title).collect();
+                (key, author.name, posts)
+            })
+            .collect();
+        assert_eq!(
+            by_author,
+            [
+                ("bors".to_string(), "Bors", vec!["A"]),
+                // named as in the newest post
+                ("ferris".to_string(), "ferris", vec!["B", "A"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn manifests_are_filled_in_from_the_defaults_above_them() {
+        let defaults = testing::MANIFEST.replace("the test team", "the shared team");
+        let fs = MemoryFs::new([
+            ("posts/defaults.yml", &*defaults),
+            ("posts/blog.yml", "title: Root\n"),
+            ("posts/inside-rust/defaults.yml", "description: Closer\n"),
+            ("posts/inside-rust/blog.yml", "title: Inside Rust\n"),
+        ]);
+        let blogs = load_from(
+            &fs,
+            Path::new("posts"),
+            &[],
+            &PostCache::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let summary = |blog: &Blog| {
+            let json = serde_json::to_value(blog).unwrap();
+            (
+                blog.title().to_string(),
+                blog.description().to_string(),
+                json["maintained_by"].as_str().unwrap().to_string(),
+            )
+        };
+        assert_eq!(
+            summary(&blogs[0]),
+            (
+                "Root".into(),
+                "Posts for the tests".into(),
+                "the shared team".into()
+            )
+        );
+        assert_eq!(
+            summary(&blogs[1]),
+            (
+                "Inside Rust".into(),
+                "Closer".into(),
+                "the shared team".into()
+            )
+        );
+    }
+
+    #[test]
+    fn indexes_are_split_into_pages() {
+        let mut fs = MemoryFs::new([("posts/blog.yml", testing::MANIFEST)]);
+        for day in 1..=4 {
+            fs.insert(
+                format!("posts/2020-01-{:02}-post.md", day),
+                post(&format!("Post {}", day), "", ""),
+            );
+        }
+        let blog = load_blog(&fs).unwrap();
+        // each page as its number, path, posts and links to the others
+        let pages = |size| -> Vec<String> {
+            blog.pages(size)
+                .into_iter()
+                .map(|page| {
+                    let titles: Vec<_> = page.posts.iter().map(|post| &*post.title).collect();
+                    format!(
+                        "{} {} {:?} {:?} {:?}",
+                        page.number, page.path, titles, page.prev, page.next
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            pages(2),
+            [
+                r#"1 index.html ["Post 4", "Post 3"] None Some("page/2/index.html")"#,
+                r#"2 page/2/index.html ["Post 2", "Post 1"] Some("index.html") None"#,
+            ]
+        );
+        assert_eq!(
+            pages(3),
+            [
+                r#"1 index.html ["Post 4", "Post 3", "Post 2"] None Some("page/2/index.html")"#,
+                r#"2 page/2/index.html ["Post 1"] Some("index.html") None"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn old_paths_redirect_to_the_post() {
+        let load = |front_matter: &str| {
+            let fs = MemoryFs::new([
+                ("posts/blog.yml", testing::MANIFEST),
+                (
+                    "posts/2020-01-01-moved.md",
+                    &*post("Moved", front_matter, ""),
+                ),
+                ("posts/2020-02-01-other.md", &*post("Other", "", "")),
+            ]);
+            redirects(&[load_blog(&fs).unwrap()]).map_err(|e| e.to_string())
+        };
+        let redirected = load("redirects: [old/path.html, /2019/12/31/moved.html]\n").unwrap();
+        let redirected: Vec<_> = redirected
+            .iter()
+            .map(|(from, to)| (&**from, &**to))
+            .collect();
+        assert_eq!(
+            redirected,
+            [
+                ("/2019/12/31/moved.html", "/2020/01/01/moved.html"),
+                ("/old/path.html", "/2020/01/01/moved.html"),
+            ]
+        );
+
+        assert_eq!(
+            load("redirects: [/2020/02/01/other.html]\n").unwrap_err(),
+            "blog post at path `posts/2020-01-01-moved.md` redirects from \
+             `/2020/02/01/other.html`, which is the url of `posts/2020-02-01-other.md`"
+        );
+        assert_eq!(
+            load("redirects: [old.html, /old.html]\n").unwrap_err(),
+            "blog posts at paths `posts/2020-01-01-moved.md` and `posts/2020-01-01-moved.md` \
+             both redirect from `/old.html`"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlink_cycles_are_visited_once() {
+        let dir = TempDir::new();
+        dir.write("blog.yml", testing::MANIFEST);
+        dir.write("inside/blog.yml", testing::MANIFEST);
+        // a link back up the tree, and another one to a sibling
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("inside/loop")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("inside"), dir.path().join("shared")).unwrap();
+
+        let dirs = find(&Disk, dir.path(), &[]).unwrap();
+        let prefixes: Vec<_> = dirs.iter().map(|(prefix, _)| prefix.clone()).collect();
+        assert_eq!(prefixes, [PathBuf::new(), PathBuf::from("inside")]);
+    }
+
+    #[test]
+    fn same_day_posts_are_ordered_by_title_then_file_name() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-a.md", &*post("Zebra", "", "")),
+            ("posts/2020-01-01-b.md", &*post("Apple", "", "")),
+            ("posts/2020-01-01-d.md", &*post("Mango", "", "")),
+            ("posts/2020-01-01-c.md", &*post("Mango", "", "")),
+            ("posts/2020-01-02-e.md", &*post("Later", "", "")),
+        ]);
+        let files = |blog: &Blog| -> Vec<String> {
+            blog.posts()
+                .iter()
+                .map(|post| post.filename.clone())
+                .collect()
+        };
+        let blog = load_blog(&fs).unwrap();
+        assert_eq!(files(&blog), ["e.md", "b.md", "c.md", "d.md", "a.md"]);
+        let shown: Vec<_> = blog.posts().iter().map(|post| post.show_year).collect();
+        assert_eq!(shown, [true, false, false, false, false]);
+        // and again, in parallel loads finishing in another order
+        for _ in 0..5 {
+            assert_eq!(files(&load_blog(&fs).unwrap()), files(&blog));
+        }
+    }
+
+    #[test]
+    fn blogs_can_shift_the_headings_of_their_posts() {
+        let mut fs = MemoryFs::new([(
+            "posts/2020-01-01-first.md",
+            &*post("First", "", "# Heading\n"),
+        )]);
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        assert!(load_blog(&fs).unwrap().posts()[0]
+            .contents
+            .contains("</h1>"));
+
+        fs.insert("posts/blog.yml", testing::manifest("heading-shift: 1\n"));
+        let blog = load_blog(&fs).unwrap();
+        let contents = &blog.posts()[0].contents;
+        assert!(contents.contains("</h2>"), "{}", contents);
+        assert!(!contents.contains("<h1"), "{}", contents);
+    }
+
+    #[test]
+    fn translations_link_posts_in_other_languages() {
+        let mut fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-hello.md",
+                &*post("Hello", "translations: {es: /2020/01/01/hola.html}\n", ""),
+            ),
+            (
+                "posts/2020-01-01-hola.md",
+                &*post(
+                    "Hola",
+                    "lang: es\ntranslations: {en: 2020/01/01/hello.html}\n",
+                    "",
+                ),
+            ),
+            ("posts/2020-02-01-other.md", &*post("Other", "", "")),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let by_title = |title: &str| {
+            blog.posts()
+                .iter()
+                .find(|post| post.title == title)
+                .unwrap()
+        };
+        let (hello, hola) = (by_title("Hello"), by_title("Hola"));
+        assert_eq!(hello.lang, "en");
+        assert_eq!(hola.lang, "es");
+        assert_eq!(
+            blog.translations(hello).into_iter().collect::<Vec<_>>(),
+            [("es", "2020/01/01/hola.html")]
+        );
+        assert_eq!(
+            blog.translations(hola).into_iter().collect::<Vec<_>>(),
+            [("en", "2020/01/01/hello.html")]
+        );
+        assert!(blog.translations(by_title("Other")).is_empty());
+
+        assert_eq!(
+            blog.languages().into_iter().collect::<Vec<_>>(),
+            ["en", "es"]
+        );
+        let titles = |lang: &str| -> Vec<String> {
+            blog.posts_in_lang(lang)
+                .iter()
+                .map(|post| post.title.clone())
+                .collect()
+        };
+        assert_eq!(titles("en"), ["Other", "Hello"]);
+        assert_eq!(titles("es"), ["Hola"]);
+
+        // translations have to exist, in the language they are declared in
+        fs.insert(
+            "posts/2020-02-01-other.md",
+            post("Other", "translations: {es: 2020/01/01/missing.html}\n", ""),
+        );
+        assert_eq!(
+            load_blog(&fs).unwrap_err().to_string(),
+            "blog post at path `posts/2020-02-01-other.md` has a `es` translation at `2020/01/01/missing.html`, but there is no post with that url"
+        );
+        fs.insert(
+            "posts/2020-02-01-other.md",
+            post("Other", "translations: {fr: 2020/01/01/hola.html}\n", ""),
+        );
+        assert_eq!(
+            load_blog(&fs).unwrap_err().to_string(),
+            "blog post at path `posts/2020-02-01-other.md` has a `fr` translation at `2020/01/01/hola.html`, but that post is in `es`"
+        );
+    }
+
+    #[test]
+    fn scheduled_posts_are_held_back_until_their_date() {
+        let mut fs = MemoryFs::new([
+            ("posts/2019-01-01-first.md", &*post("First", "", "")),
+            ("posts/2020-01-01-second.md", &*post("Second", "", "")),
+            ("posts/2999-01-01-later.md", &*post("Later", "", "")),
+        ]);
+        let titles = |blog: &Blog| -> Vec<String> {
+            blog.posts().iter().map(|post| post.title.clone()).collect()
+        };
+        // blogs publish them right away unless they schedule them
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        assert_eq!(
+            titles(&load_blog(&fs).unwrap()),
+            ["Later", "Second", "First"]
+        );
+
+        fs.insert(
+            "posts/blog.yml",
+            testing::manifest("schedule-posts: true\n"),
+        );
+        let blog = load_blog(&fs).unwrap();
+        assert_eq!(titles(&blog), ["Second", "First"]);
+        // the year is shown on the newest post which is published
+        let shown: Vec<_> = blog.posts().iter().map(|post| post.show_year).collect();
+        assert_eq!(shown, [true, true]);
+
+        let config = Config {
+            preview_future: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            titles(&load_blog_with(&fs, &config).unwrap()),
+            ["Later", "Second", "First"]
+        );
+    }
+
+    #[test]
+    fn featured_posts_are_listed_apart_too() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-first.md",
+                &*post("First", "featured: true\n", ""),
+            ),
+            ("posts/2020-02-01-second.md", &*post("Second", "", "")),
+            (
+                "posts/2020-03-01-third.md",
+                &*post("Third", "featured: true\n", ""),
+            ),
+            (
+                "posts/2020-04-01-fourth.md",
+                &*post("Fourth", "featured: false\n", ""),
+            ),
+            ("posts/2020-05-01-fifth.md", &*post("Fifth", "", "")),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let featured: Vec<_> = blog
+            .featured_posts()
+            .iter()
+            .map(|post| &*post.title)
+            .collect();
+        assert_eq!(featured, ["Third", "First"]);
+        assert_eq!(blog.posts().len(), 5);
+    }
+
+    #[test]
+    fn hidden_and_excluded_directories_are_skipped() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/drafts/blog.yml", testing::MANIFEST),
+            ("posts/_templates/blog.yml", testing::MANIFEST),
+            ("posts/.git/blog.yml", testing::MANIFEST),
+            ("posts/inside-rust/blog.yml", testing::MANIFEST),
+            ("posts/inside-rust/old/blog.yml", testing::MANIFEST),
+            ("posts/inside-rust/drafts/blog.yml", testing::MANIFEST),
+        ]);
+        let prefixes = |exclude: &[&str]| -> Vec<PathBuf> {
+            let exclude: Vec<_> = exclude.iter().map(|p| Pattern::new(p).unwrap()).collect();
+            find(&fs, Path::new("posts"), &exclude)
+                .unwrap()
+                .into_iter()
+                .map(|(prefix, _)| prefix)
+                .collect()
+        };
+        assert_eq!(
+            prefixes(&[]),
+            // in the order of the paths of the directories and their files
+            [
+                "_templates",
+                "",
+                "drafts",
+                "inside-rust",
+                "inside-rust/drafts",
+                "inside-rust/old",
+            ]
+            .map(PathBuf::from)
+        );
+        // by name anywhere, or by path from the base
+        assert_eq!(
+            prefixes(&["drafts", "_*", "inside-rust/old"]),
+            ["", "inside-rust"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn team_blogs_have_to_name_their_team() {
+        let team_blog = testing::MANIFEST.replace("requires-team: false", "requires-team: true");
+        let mut fs = MemoryFs::new([("posts/blog.yml", &*team_blog)]);
+        let err = "invalid manifest `posts/blog.yml`: requires-team is set, but the blog doesn't name its `team`";
+        assert_eq!(load_blog(&fs).unwrap_err().to_string(), err);
+        fs.insert("posts/blog.yml", format!("{}team: \"  \"\n", team_blog));
+        assert_eq!(load_blog(&fs).unwrap_err().to_string(), err);
+
+        fs.insert("posts/blog.yml", format!("{}team: compiler\n", team_blog));
+        assert!(load_blog(&fs).is_ok());
+        // other blogs don't need one
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        assert!(load_blog(&fs).is_ok());
+    }
+
+    #[test]
+    fn blogs_can_enable_math() {
+        let mut fs = MemoryFs::new([("posts/2020-01-01-first.md", &*post("First", "", "$x$\n"))]);
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        assert_eq!(load_blog(&fs).unwrap().posts()[0].contents, "<p>$x$</p>\n");
+
+        fs.insert("posts/blog.yml", testing::manifest("math: true\n"));
+        assert_eq!(
+            load_blog(&fs).unwrap().posts()[0].contents,
+            "<p><span class=\"math inline\">\\(x\\)</span></p>\n"
+        );
+    }
+
+    #[test]
+    fn blogs_know_the_dates_of_their_first_and_last_posts() {
+        let mut fs = MemoryFs::new([
+            (
+                "posts/2015-05-15-first.md",
+                &*post("First", "order: 2\n", ""),
+            ),
+            (
+                "posts/2020-02-01-second.md",
+                &*post("Second", "order: 1\n", ""),
+            ),
+            ("posts/2018-01-01-third.md", &*post("Third", "", "")),
+        ]);
+        let dates = |fs: &MemoryFs| {
+            let blog = load_blog(fs).unwrap();
+            (blog.first_post_date(), blog.last_post_date())
+        };
+        let expected = (
+            Some(NaiveDate::from_ymd(2015, 5, 15)),
+            Some(NaiveDate::from_ymd(2020, 2, 1)),
+        );
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        assert_eq!(dates(&fs), expected);
+        // whatever the order of the posts
+        fs.insert("posts/blog.yml", testing::manifest("sort-by: order\n"));
+        assert_eq!(dates(&fs), expected);
+
+        let fs = MemoryFs::new([("posts/blog.yml", testing::MANIFEST)]);
+        assert_eq!(dates(&fs), (None, None));
+    }
+
+    #[test]
+    fn related_posts_share_the_most_tags() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-post.md",
+                &*post("Post", "tags: [a, b, c]\n", ""),
+            ),
+            ("posts/2020-02-01-one.md", &*post("One", "tags: [a]\n", "")),
+            (
+                "posts/2020-03-01-two.md",
+                &*post("Two", "tags: [a, b]\n", ""),
+            ),
+            (
+                "posts/2020-04-01-newer-one.md",
+                &*post("Newer one", "tags: [c]\n", ""),
+            ),
+            (
+                "posts/2020-05-01-other.md",
+                &*post("Other", "tags: [d]\n", ""),
+            ),
+            ("posts/2020-06-01-untagged.md", &*post("Untagged", "", "")),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let by_title = |title: &str| {
+            blog.posts()
+                .iter()
+                .find(|post| post.title == title)
+                .unwrap()
+        };
+        let related = |title: &str, limit: usize| -> Vec<&str> {
+            blog.related_posts(by_title(title), limit)
+                .iter()
+                .map(|post| &*post.title)
+                .collect()
+        };
+        assert_eq!(related("Post", 3), ["Two", "Newer one", "One"]);
+        assert_eq!(related("Post", 2), ["Two", "Newer one"]);
+        assert_eq!(related("One", 3), ["Two", "Post"]);
+        assert_eq!(related("Other", 3), Vec::<&str>::new());
+        assert_eq!(related("Untagged", 3), Vec::<&str>::new());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn posts_with_file_names_which_are_not_utf8_are_reported() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = TempDir::new();
+        dir.write("posts/blog.yml", testing::MANIFEST);
+        let name = std::ffi::OsStr::from_bytes(b"2020-01-01-caf\xe9.md");
+        let path = dir.path().join("posts").join(name);
+        std::fs::write(&path, post("Café", "", "")).unwrap();
+
+        let err = Blog::load_from(
+            &Disk,
+            PathBuf::new(),
+            &dir.path().join("posts"),
+            &PostCache::default(),
+            &Config::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "blog post at path `{}` should have a file name which is valid UTF-8",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn blogs_can_sort_posts_by_their_order() {
+        let mut fs = MemoryFs::new([
+            (
+                "posts/2020-01-01-intro.md",
+                &*post("Intro", "order: 1\n", ""),
+            ),
+            (
+                "posts/2020-03-01-setup.md",
+                &*post("Setup", "order: 2\n", ""),
+            ),
+            (
+                "posts/2020-02-01-basics.md",
+                &*post("Basics", "order: 2\n", ""),
+            ),
+            ("posts/2020-06-01-appendix.md", &*post("Appendix", "", "")),
+            ("posts/2020-05-01-notes.md", &*post("Notes", "", "")),
+            (
+                "posts/2020-04-01-advanced.md",
+                &*post("Advanced", "order: 3\n", ""),
+            ),
+        ]);
+        let titles = |fs: &MemoryFs| -> Vec<String> {
+            load_blog(fs)
+                .unwrap()
+                .posts()
+                .iter()
+                .map(|post| post.title.clone())
+                .collect()
+        };
+        // by date by default, whatever their order
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        assert_eq!(
+            titles(&fs),
+            ["Appendix", "Notes", "Advanced", "Setup", "Basics", "Intro"]
+        );
+        // ascending, then by date, with the posts without an order last
+        fs.insert("posts/blog.yml", testing::manifest("sort-by: order\n"));
+        assert_eq!(
+            titles(&fs),
+            ["Intro", "Setup", "Basics", "Advanced", "Appendix", "Notes"]
+        );
+    }
+
+    #[test]
+    fn posts_can_be_found_by_url() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-first.md", &*post("First", "", "")),
+            ("posts/2020-02-01-second.md", &*post("Second", "", "")),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let title = |url: &str| blog.find_post(url).map(|post| &*post.title);
+        assert_eq!(title("2020/01/01/first.html"), Some("First"));
+        assert_eq!(title("/2020/02/01/second.html"), Some("Second"));
+        assert_eq!(title("2020/02/01/second.html/"), Some("Second"));
+        assert_eq!(title("2020/03/01/third.html"), None);
+        assert_eq!(title("2020/01/01/first"), None);
+    }
+
+    #[test]
+    fn sources_written_on_windows_are_read_like_the_others() {
+        let manifest = format!("\u{feff}{}", testing::MANIFEST.replace('\n', "\r\n"));
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", &*manifest),
+            (
+                "posts/2020-01-01-first.md",
+                "\u{feff}---\r\ntitle: First\r\nauthors: Ferris\r\n---\r\nHello\r\n\r\nWorld\r\n",
+            ),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        assert_eq!(blog.title(), "Test Blog");
+        let post = &blog.posts()[0];
+        assert_eq!(post.title, "First");
+        assert_eq!(post.authors, ["Ferris"]);
+        assert_eq!(post.contents, "<p>Hello</p>\n<p>World</p>\n");
+    }
+
+    #[test]
+    fn the_parts_of_a_series_are_numbered_and_linked() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-one.md",
+                &*post("One", "series: Async\n", ""),
+            ),
+            ("posts/2020-02-01-other.md", &*post("Other", "", "")),
+            (
+                "posts/2020-03-01-two.md",
+                &*post("Two", "series: Async\n", ""),
+            ),
+            (
+                "posts/2020-04-01-two-b.md",
+                &*post("Elsewhere", "series: GATs\n", ""),
+            ),
+            (
+                "posts/2020-05-01-three.md",
+                &*post("Three", "series: Async\n", ""),
+            ),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let part = |title: &str| {
+            let post = blog
+                .posts()
+                .iter()
+                .find(|post| post.title == title)
+                .unwrap();
+            post.series_part.as_ref().map(|part| {
+                let title = |link: &Option<PostLink>| link.as_ref().map(|link| link.title.clone());
+                (part.part, part.parts, title(&part.prev), title(&part.next))
+            })
+        };
+        let some = |title: &str| Some(title.to_string());
+        assert_eq!(part("One"), Some((1, 3, None, some("Two"))));
+        assert_eq!(part("Two"), Some((2, 3, some("One"), some("Three"))));
+        assert_eq!(part("Three"), Some((3, 3, some("Two"), None)));
+        assert_eq!(part("Elsewhere"), Some((1, 1, None, None)));
+        assert_eq!(part("Other"), None);
+        let two = blog
+            .posts()
+            .iter()
+            .find(|post| post.title == "Two")
+            .unwrap();
+        let next = two.series_part.as_ref().unwrap().next.as_ref().unwrap();
+        assert_eq!(next.url, "2020/05/01/three.html");
+    }
+
+    #[test]
+    fn posts_link_to_the_previous_and_next_ones() {
+        let mut fs = MemoryFs::new([
+            (
+                "posts/2020-01-01-first.md",
+                &*post("First", "order: 3\n", ""),
+            ),
+            (
+                "posts/2020-02-01-second.md",
+                &*post("Second", "order: 1\n", ""),
+            ),
+            (
+                "posts/2020-03-01-third.md",
+                &*post("Third", "order: 2\n", ""),
+            ),
+        ]);
+        let neighbors = |fs: &MemoryFs| -> Vec<(String, Option<String>, Option<String>)> {
+            let blog = load_blog(fs).unwrap();
+            let mut neighbors: Vec<_> = blog
+                .posts()
+                .iter()
+                .map(|post| {
+                    let url = |link: &Option<PostLink>| link.as_ref().map(|link| link.url.clone());
+                    (post.title.clone(), url(&post.prev), url(&post.next))
+                })
+                .collect();
+            neighbors.sort();
+            neighbors
+        };
+        let url = |url: &str| Some(url.to_string());
+        let expected = vec![
+            ("First".to_string(), None, url("2020/02/01/second.html")),
+            (
+                "Second".to_string(),
+                url("2020/01/01/first.html"),
+                url("2020/03/01/third.html"),
+            ),
+            ("Third".to_string(), url("2020/02/01/second.html"), None),
+        ];
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        assert_eq!(neighbors(&fs), expected);
+        // by date even when the blog is sorted otherwise
+        fs.insert("posts/blog.yml", testing::manifest("sort-by: order\n"));
+        assert_eq!(neighbors(&fs), expected);
+
+        let blog = load_blog(&fs).unwrap();
+        let post = serde_json::to_value(&blog.posts()[0]).unwrap();
+        assert_eq!(post["prev"]["url"], "2020/01/01/first.html");
+        assert_eq!(post["next"]["title"], "Third");
+    }
+
+    #[test]
+    fn blogs_can_choose_the_urls_of_their_posts() {
+        let mut fs = MemoryFs::new([("posts/2020-01-02-first.md", &*post("First", "", ""))]);
+        let url = |fs: &MemoryFs| load_blog(fs).map(|blog| blog.posts()[0].url.clone());
+        fs.insert("posts/blog.yml", testing::MANIFEST);
+        assert_eq!(url(&fs).unwrap(), "2020/01/02/first.html");
+        fs.insert(
+            "posts/blog.yml",
+            testing::manifest("permalink: \"/:year/:slug/\"\n"),
+        );
+        assert_eq!(url(&fs).unwrap(), "2020/first/");
+        fs.insert(
+            "posts/blog.yml",
+            testing::manifest("permalink: \":year-:month-:day-:slug.html\"\n"),
+        );
+        assert_eq!(url(&fs).unwrap(), "2020-01-02-first.html");
+
+        fs.insert(
+            "posts/blog.yml",
+            testing::manifest("permalink: \"/:year/:title/\"\n"),
+        );
+        assert_eq!(
+            url(&fs).unwrap_err().to_string(),
+            "invalid manifest `posts/blog.yml`: unknown token `:title` in permalink `/:year/:title/`, expected `:year`, `:month`, `:day` or `:slug`"
+        );
+        fs.insert(
+            "posts/blog.yml",
+            testing::manifest("permalink: \"/:year/:month/\"\n"),
+        );
+        assert_eq!(
+            url(&fs).unwrap_err().to_string(),
+            "invalid manifest `posts/blog.yml`: permalink `/:year/:month/` doesn't contain `:slug`"
+        );
+    }
+
+    #[test]
+    fn posts_are_rebuilt_when_their_includes_change() {
+        let mut fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-first.md",
+                &post("First", "", "{% include disclaimer.md %}\n"),
+            ),
+            ("posts/2020-02-01-second.md", &post("Second", "", "Hello")),
+            ("posts/_includes/disclaimer.md", "Not advice."),
+        ]);
+        let dir = TempDir::new();
+        let cache_file = dir.path().join("posts.json");
+        let load = |fs: &MemoryFs| {
+            let cache = PostCache::load(&cache_file);
+            let blog = Blog::load_from(
+                fs,
+                PathBuf::new(),
+                Path::new("posts"),
+                &cache,
+                &Config::default(),
+            )
+            .unwrap();
+            cache.save(&cache_file).unwrap();
+            (blog, cache.parsed())
+        };
+
+        let (blog, parsed) = load(&fs);
+        assert_eq!(parsed, 2);
+        // the includes aren't posts themselves
+        assert_eq!(blog.posts().len(), 2);
+        assert_eq!(blog.posts()[1].contents, "<p>Not advice.</p>\n");
+        assert_eq!(load(&fs).1, 0);
+
+        fs.insert("posts/_includes/disclaimer.md", "Still not advice.");
+        let (blog, parsed) = load(&fs);
+        assert_eq!(parsed, 1);
+        assert_eq!(blog.posts()[1].contents, "<p>Still not advice.</p>\n");
+    }
+
+    #[test]
+    fn extra_styles_have_to_be_files_of_the_blog() {
+        let style = |path: &str| testing::manifest(&format!("extra-styles: [\"{}\"]\n", path));
+
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", &*style("styles/blog.css")),
+            ("posts/styles/blog.css", "body {}"),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        assert_eq!(blog.extra_styles(), ["styles/blog.css"]);
+
+        let fs = MemoryFs::new([("posts/blog.yml", &*style("styles/gone.css"))]);
+        assert_eq!(
+            load_blog(&fs).unwrap_err().to_string(),
+            "manifest at path `posts/blog.yml` lists the stylesheet `styles/gone.css`, \
+             which isn't a file in `posts`"
+        );
+
+        for path in ["/styles/blog.css", "../styles/blog.css"] {
+            let fs = MemoryFs::new([("posts/blog.yml", &*style(path))]);
+            assert_eq!(
+                load_blog(&fs).unwrap_err().to_string(),
+                format!(
+                    "invalid manifest `posts/blog.yml`: extra style `{}` should be a path \
+                     relative to the directory of the blog",
+                    path
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn posts_of_other_blogs_know_their_source_path() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/inside-rust/blog.yml", testing::MANIFEST),
+            (
+                "posts/inside-rust/2020-01-01-inside.md",
+                &*post("Inside", "", ""),
+            ),
+        ]);
+        let blogs = load_from(
+            &fs,
+            Path::new("posts"),
+            &[],
+            &PostCache::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        let post = &blogs[1].posts()[0];
+        assert_eq!(post.source_path, "posts/inside-rust/2020-01-01-inside.md");
+        assert_eq!(
+            serde_json::to_value(post).unwrap()["source_path"],
+            "posts/inside-rust/2020-01-01-inside.md"
+        );
+    }
+
+    #[test]
+    fn single_posts_are_reloaded_from_the_filesystem() {
+        let mut fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-first.md", &*post("First", "", "")),
+            ("posts/2020-02-01-second.md", &*post("Second", "", "")),
+        ]);
+        let mut blog = load_blog(&fs).unwrap();
+        let titles = |blog: &Blog| -> Vec<String> {
+            blog.posts().iter().map(|post| post.title.clone()).collect()
+        };
+        let reload = |blog: &mut Blog, fs: &MemoryFs, path: &str| {
+            blog.reload_post(
+                fs,
+                Path::new(path),
+                &PostCache::default(),
+                &Config::default(),
+            )
+            .unwrap();
+        };
+
+        fs.insert("posts/2020-01-01-first.md", post("Changed", "", ""));
+        reload(&mut blog, &fs, "posts/2020-01-01-first.md");
+        assert_eq!(titles(&blog), ["Second", "Changed"]);
+        // the posts around it link to it with its new title
+        assert_eq!(blog.posts()[0].prev.as_ref().unwrap().title, "Changed");
+
+        fs.insert("posts/2020-03-01-third.md", post("Third", "", ""));
+        reload(&mut blog, &fs, "posts/2020-03-01-third.md");
+        assert_eq!(titles(&blog), ["Third", "Second", "Changed"]);
+
+        fs.remove("posts/2020-02-01-second.md");
+        reload(&mut blog, &fs, "posts/2020-02-01-second.md");
+        assert_eq!(titles(&blog), ["Third", "Changed"]);
+
+        // files which aren't posts are ignored
+        fs.insert("posts/notes.txt", "not a post");
+        reload(&mut blog, &fs, "posts/notes.txt");
+        assert_eq!(titles(&blog), ["Third", "Changed"]);
+    }
 }
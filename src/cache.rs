@@ -0,0 +1,150 @@
+use super::config::Config;
+use super::posts::Post;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Bump this whenever the way posts are parsed or rendered changes, so
+/// existing caches are thrown away.
+static CACHE_VERSION: u32 = 30;
+
+/// Posts parsed by previous builds, so unchanged files don't have to be
+/// parsed and rendered again.
+///
+/// Only the entries used by the current build are written back, which keeps
+/// removed posts from piling up in the cache file.
+#[derive(Default)]
+pub(crate) struct PostCache {
+    previous: HashMap<PathBuf, CachedPost>,
+    current: Mutex<HashMap<PathBuf, CachedPost>>,
+    /// How many posts had to be parsed, for the tests to tell.
+    #[cfg(test)]
+    parsed: std::sync::atomic::AtomicUsize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: String,
+    posts: HashMap<PathBuf, CachedPost>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedPost {
+    fingerprint: u64,
+    post: Post,
+}
+
+impl PostCache {
+    /// Load the cache written by a previous build. A missing, unreadable or
+    /// outdated cache file results in an empty cache.
+    pub(crate) fn load(path: &Path) -> Self {
+        let previous = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<CacheFile>(&content).ok())
+            .filter(|file| file.version == version())
+            .map(|file| file.posts)
+            .unwrap_or_default();
+
+        PostCache {
+            previous,
+            ..PostCache::default()
+        }
+    }
+
+    /// The post previously parsed from `path`, if its sources didn't change since.
+    pub(crate) fn get(&self, path: &Path, fingerprint: u64) -> Option<Post> {
+        let cached = self.previous.get(path)?;
+        if cached.fingerprint != fingerprint {
+            return None;
+        }
+        let mut post = cached.post.clone();
+        post.path = path.to_path_buf();
+        self.current
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), cached.clone());
+        Some(post)
+    }
+
+    /// Record a post which was parsed, as it wasn't in the cache.
+    pub(crate) fn insert(&self, path: &Path, fingerprint: u64, post: &Post) {
+        #[cfg(test)]
+        self.parsed
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.current.lock().unwrap().insert(
+            path.to_path_buf(),
+            CachedPost {
+                fingerprint,
+                post: post.clone(),
+            },
+        );
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = CacheFile {
+            version: version(),
+            posts: self.current.lock().unwrap().clone(),
+        };
+        std::fs::write(path, serde_json::to_string(&file)?)?;
+        Ok(())
+    }
+}
+
+/// A fingerprint of everything a post is built from: its own source, the
+/// manifest of its blog and the config of the build.
+pub(crate) fn fingerprint(post_source: &str, manifest_source: &str, config: &Config) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    post_source.hash(&mut hasher);
+    manifest_source.hash(&mut hasher);
+    config.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+impl PostCache {
+    /// The number of posts parsed rather than taken from the cache so far.
+    pub(crate) fn parsed(&self) -> usize {
+        self.parsed.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+fn version() -> String {
+    format!("{}-{}", env!("CARGO_PKG_VERSION"), CACHE_VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_config_is_part_of_the_fingerprint() {
+        let staging = Config {
+            base_url: "https://staging.example/".into(),
+            ..Config::default()
+        };
+        let editable = Config {
+            edit_url_base: Some("https://github.com/rust-lang/blog/edit/master/".into()),
+            ..Config::default()
+        };
+        let fingerprint = |config: &Config| fingerprint("post", "manifest", config);
+        let default = fingerprint(&Config::default());
+        assert_eq!(default, fingerprint(&Config::default()));
+        assert_ne!(default, fingerprint(&staging));
+        assert_ne!(default, fingerprint(&editable));
+
+        // which posts are published doesn't change how they are rendered
+        let previewing = Config {
+            include_drafts: true,
+            preview_future: true,
+            ..Config::default()
+        };
+        assert_eq!(default, fingerprint(&previewing));
+    }
+}
@@ -0,0 +1,94 @@
+//! The settings of a build which don't come from the sources of the site, but
+//! from the environment it is built in.
+
+use std::hash::{Hash, Hasher};
+
+static DEFAULT_BASE_URL: &str = "https://blog.rust-lang.org/";
+static BASE_URL_VAR: &str = "BASE_URL";
+static EDIT_URL_BASE_VAR: &str = "EDIT_URL_BASE";
+static INCLUDE_DRAFTS_VAR: &str = "INCLUDE_DRAFTS";
+static PREVIEW_FUTURE_VAR: &str = "PREVIEW_FUTURE";
+
+/// The urls in here change how posts are rendered, so they are part of the
+/// fingerprint of cached posts. Which posts are published doesn't change how
+/// any of them is rendered, so it's left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Config {
+    /// Where the site is hosted, always ending with a `/` like the prefixes of
+    /// blogs. The `BASE_URL` environment variable generates it for somewhere
+    /// else, like a staging deployment.
+    pub(crate) base_url: String,
+    /// Where the sources of the posts can be edited, like
+    /// `https://github.com/rust-lang/blog.rust-lang.org/edit/master/`, which
+    /// posts then link to. It's set with the `EDIT_URL_BASE` environment
+    /// variable, and always ends with a `/`.
+    pub(crate) edit_url_base: Option<String>,
+    /// Whether drafts are published too, e.g. to preview them locally. It's
+    /// set with the `INCLUDE_DRAFTS` environment variable.
+    pub(crate) include_drafts: bool,
+    /// Whether posts scheduled for later are published already, in blogs
+    /// which schedule them. It's set with the `PREVIEW_FUTURE` environment
+    /// variable.
+    pub(crate) preview_future: bool,
+}
+
+impl Hash for Config {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.base_url.hash(state);
+        self.edit_url_base.hash(state);
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            edit_url_base: None,
+            include_drafts: false,
+            preview_future: false,
+        }
+    }
+}
+
+impl Config {
+    /// The config set by the environment variables, with the defaults for the
+    /// ones which aren't set or are empty.
+    pub(crate) fn from_env() -> Self {
+        let var = |name| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        Config {
+            base_url: var(BASE_URL_VAR).map_or_else(
+                || DEFAULT_BASE_URL.to_string(),
+                |url| with_trailing_slash(&url),
+            ),
+            edit_url_base: var(EDIT_URL_BASE_VAR).map(|url| with_trailing_slash(&url)),
+            include_drafts: std::env::var_os(INCLUDE_DRAFTS_VAR).is_some(),
+            preview_future: std::env::var_os(PREVIEW_FUTURE_VAR).is_some(),
+        }
+    }
+}
+
+fn with_trailing_slash(url: &str) -> String {
+    format!("{}/", url.trim().trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_end_with_a_single_slash() {
+        assert_eq!(
+            with_trailing_slash("https://example.com"),
+            "https://example.com/"
+        );
+        assert_eq!(
+            with_trailing_slash(" https://example.com/staging// "),
+            "https://example.com/staging/"
+        );
+        assert!(Config::default().base_url.ends_with(".org/"));
+    }
+}
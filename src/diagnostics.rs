@@ -0,0 +1,73 @@
+//! Problems found while generating the site. They are printed as they are
+//! found, and can also be written as a JSON report for tools like CI to read.
+
+use serde_derive::Serialize;
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Severity {
+    Warning,
+    /// Something which fails the build.
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+struct Diagnostic {
+    /// What kind of problem this is, e.g. `broken-link`.
+    code: &'static str,
+    severity: Severity,
+    message: String,
+    /// The source file the problem is in.
+    path: Option<PathBuf>,
+}
+
+#[derive(Default)]
+pub(crate) struct Diagnostics {
+    reported: RefCell<Vec<Diagnostic>>,
+}
+
+impl Diagnostics {
+    /// Record a problem and print it.
+    pub(crate) fn report(
+        &self,
+        code: &'static str,
+        severity: Severity,
+        path: Option<&Path>,
+        message: String,
+    ) {
+        match severity {
+            Severity::Warning => eprintln!("warning: {}", message),
+            Severity::Error => eprintln!("error: {}", message),
+        }
+        self.reported.borrow_mut().push(Diagnostic {
+            code,
+            severity,
+            message,
+            path: path.map(Path::to_path_buf),
+        });
+    }
+
+    /// The number of problems of the given severity reported so far.
+    pub(crate) fn count(&self, severity: Severity) -> usize {
+        self.reported
+            .borrow()
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+
+    /// Write every problem reported so far to `path`, as a JSON array.
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(
+            path,
+            serde_json::to_string_pretty(&*self.reported.borrow())?,
+        )?;
+        Ok(())
+    }
+}
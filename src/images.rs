@@ -0,0 +1,143 @@
+//! The dimensions of images, read from their headers. Only the formats posts
+//! use are supported: PNG, GIF, JPEG and SVG.
+
+use regex::Regex;
+use std::path::Path;
+
+/// The width and height of the image in `path`, in pixels, or `None` if it
+/// can't be read or isn't in a supported format.
+pub(crate) fn dimensions(path: &Path) -> Option<(u32, u32)> {
+    let data = std::fs::read(path).ok()?;
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png(&data)
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        gif(&data)
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        jpeg(&data)
+    } else {
+        svg(std::str::from_utf8(&data).ok()?)
+    }
+}
+
+/// The size is in the `IHDR` chunk, which always comes first.
+fn png(data: &[u8]) -> Option<(u32, u32)> {
+    if data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    Some((be_u32(data.get(16..20)?), be_u32(data.get(20..24)?)))
+}
+
+fn gif(data: &[u8]) -> Option<(u32, u32)> {
+    let width = u16::from_le_bytes([*data.get(6)?, *data.get(7)?]);
+    let height = u16::from_le_bytes([*data.get(8)?, *data.get(9)?]);
+    Some((width.into(), height.into()))
+}
+
+/// The size is in the start of frame segment, found by skipping the segments
+/// before it.
+fn jpeg(data: &[u8]) -> Option<(u32, u32)> {
+    let mut i = 2;
+    loop {
+        if *data.get(i)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(i + 1)?;
+        match marker {
+            // padding before a marker
+            0xff => {
+                i += 1;
+                continue;
+            }
+            // markers without a segment
+            0xd0..=0xd9 | 0x01 => {
+                i += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let length = usize::from(be_u16(data.get(i + 2..i + 4)?));
+        // all start of frame markers, skipping DHT, JPG and DAC which share the range
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            let height = be_u16(data.get(i + 5..i + 7)?);
+            let width = be_u16(data.get(i + 7..i + 9)?);
+            return Some((width.into(), height.into()));
+        }
+        i += 2 + length;
+    }
+}
+
+/// The `width` and `height` of the root element when they are in pixels, or
+/// else its `viewBox`.
+fn svg(source: &str) -> Option<(u32, u32)> {
+    lazy_static::lazy_static! {
+        static ref ROOT: Regex = Regex::new(r"<svg\s[^>]*>").unwrap();
+        static ref WIDTH: Regex = Regex::new(r#"\swidth="([\d.]+)(?:px)?""#).unwrap();
+        static ref HEIGHT: Regex = Regex::new(r#"\sheight="([\d.]+)(?:px)?""#).unwrap();
+        static ref VIEW_BOX: Regex =
+            Regex::new(r#"\sviewBox="[-\d.]+[\s,]+[-\d.]+[\s,]+([\d.]+)[\s,]+([\d.]+)""#).unwrap();
+    }
+    let root = ROOT.find(source)?.as_str();
+    match (WIDTH.captures(root), HEIGHT.captures(root)) {
+        (Some(width), Some(height)) => Some((pixels(&width[1])?, pixels(&height[1])?)),
+        _ => {
+            let view_box = VIEW_BOX.captures(root)?;
+            Some((pixels(&view_box[1])?, pixels(&view_box[2])?))
+        }
+    }
+}
+
+fn pixels(number: &str) -> Option<u32> {
+    number.parse::<f64>().ok().map(|n| n.round() as u32)
+}
+
+fn be_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::TempDir;
+    use super::*;
+
+    /// The header of a PNG image, which is all that is read of it.
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn the_dimensions_of_images_are_read_from_their_header() {
+        let dir = TempDir::new();
+        let read = |name: &str, data: &[u8]| {
+            dir.write(name, data);
+            dimensions(&dir.path().join(name))
+        };
+        assert_eq!(read("a.png", &png_header(640, 480)), Some((640, 480)));
+        assert_eq!(read("a.gif", b"GIF89a\x80\x02\xe0\x01"), Some((640, 480)));
+        // an APP0 segment before the start of frame
+        assert_eq!(
+            read(
+                "a.jpg",
+                b"\xff\xd8\xff\xe0\x00\x04\x00\x00\xff\xc0\x00\x11\x08\x01\xe0\x02\x80"
+            ),
+            Some((640, 480))
+        );
+        assert_eq!(
+            read("a.svg", br#"<svg xmlns="x" width="64px" height="48.4">"#),
+            Some((64, 48))
+        );
+        assert_eq!(
+            read("b.svg", br#"<svg viewBox="0 0 640 480">"#),
+            Some((640, 480))
+        );
+        assert_eq!(read("a.txt", b"not an image"), None);
+        assert_eq!(read("truncated.png", b"\x89PNG\r\n\x1a\n"), None);
+        assert_eq!(dimensions(&dir.path().join("missing.png")), None);
+    }
+}
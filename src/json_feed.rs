@@ -0,0 +1,146 @@
+use super::blogs::Blog;
+use super::links;
+use serde_derive::Serialize;
+
+static VERSION: &str = "https://jsonfeed.org/version/1.1";
+
+/// A [JSON Feed](https://www.jsonfeed.org/version/1.1/) document for a single blog.
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonFeed {
+    version: &'static str,
+    title: String,
+    home_page_url: String,
+    feed_url: String,
+    description: String,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_html: Option<String>,
+    content_text: String,
+    summary: String,
+    date_published: String,
+    date_modified: String,
+    authors: Vec<JsonFeedAuthor>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFeedAuthor {
+    name: String,
+}
+
+impl JsonFeed {
+    pub(crate) fn new(blog: &Blog, feed_path: &str, length: usize) -> Self {
+        let home_page_url = blog.absolute_url("");
+        let items = blog
+            .posts()
+            .iter()
+            .take(length)
+            .map(|post| {
+                let url = format!("{}{}", home_page_url, post.url);
+                let source = format!("{}{}", blog.url_prefix(), post.url);
+                // without the full content, the summary stands in for it
+                let (content_html, content_text) = if blog.feed_full_content() {
+                    (
+                        Some(links::make_absolute(
+                            &post.contents,
+                            blog.base_url(),
+                            &source,
+                        )),
+                        post.plain_text().to_string(),
+                    )
+                } else {
+                    (None, post.description.clone())
+                };
+                JsonFeedItem {
+                    id: url.clone(),
+                    url,
+                    title: post.title.clone(),
+                    content_html,
+                    content_text,
+                    summary: post.description.clone(),
+                    date_published: post.published.clone(),
+                    date_modified: post.updated.clone(),
+                    authors: post
+                        .authors
+                        .iter()
+                        .map(|name| JsonFeedAuthor { name: name.clone() })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        JsonFeed {
+            version: VERSION,
+            title: blog.title().into(),
+            feed_url: format!("{}{}", home_page_url, feed_path),
+            home_page_url,
+            description: blog.description().into(),
+            items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{self, load_blog, post};
+    use super::super::vfs::MemoryFs;
+    use super::*;
+
+    #[test]
+    fn feeds_have_the_fields_json_feed_requires() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-first.md",
+                &*post(
+                    "First",
+                    "authors: [Ferris, Bors]\n",
+                    "Hello [there](/there.html)",
+                ),
+            ),
+            ("posts/2020-02-01-second.md", &*post("Second", "", "World")),
+        ]);
+        let blog = load_blog(&fs).unwrap();
+        let feed = serde_json::to_value(JsonFeed::new(&blog, "feed.json", 10)).unwrap();
+
+        assert_eq!(feed["version"], VERSION);
+        assert_eq!(feed["title"], "Test Blog");
+        assert_eq!(feed["description"], "Posts for the tests");
+        assert_eq!(feed["home_page_url"], "https://blog.rust-lang.org/");
+        assert_eq!(feed["feed_url"], "https://blog.rust-lang.org/feed.json");
+        let items = feed["items"].as_array().unwrap();
+        assert_eq!(items.len(), blog.posts().len());
+        for item in items {
+            // every item needs an id, and some content
+            assert!(item["id"].is_string());
+            assert!(item["content_html"].is_string() || item["content_text"].is_string());
+        }
+
+        let first = &items[1];
+        assert_eq!(
+            first["id"],
+            "https://blog.rust-lang.org/2020/01/01/first.html"
+        );
+        assert_eq!(first["title"], "First");
+        assert_eq!(first["date_published"], "2020-01-01T00:00:00+00:00");
+        assert_eq!(
+            first["content_html"],
+            "<p>Hello <a href=\"https://blog.rust-lang.org/there.html\">there</a></p>\n"
+        );
+        assert_eq!(
+            first["authors"],
+            serde_json::json!([{ "name": "Ferris" }, { "name": "Bors" }])
+        );
+
+        // and feeds can be shorter than their blog
+        let feed = JsonFeed::new(&blog, "feed.json", 1);
+        assert_eq!(feed.items.len(), 1);
+        assert_eq!(feed.items[0].title, "Second");
+    }
+}
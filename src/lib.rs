@@ -1,23 +1,131 @@
+mod assets;
 mod blogs;
+mod cache;
+mod config;
+mod diagnostics;
+mod images;
+mod json_feed;
+mod links;
+mod markdown;
+mod math;
 mod posts;
+mod slugs;
+#[cfg(test)]
+mod testing;
+mod vfs;
 
-use self::blogs::Blog;
+use self::blogs::{AuthorPosts, Blog};
+use self::cache::PostCache;
+use self::config::Config;
+use self::diagnostics::{Diagnostics, Severity};
+use self::json_feed::JsonFeed;
 use self::posts::Post;
-use chrono::Timelike;
-use handlebars::{handlebars_helper, Handlebars};
+use self::slugs::slugify;
+use self::vfs::Disk;
+use chrono::{Datelike, TimeZone, Timelike};
+use glob::Pattern;
+use handlebars::{handlebars_helper, Context, Handlebars, Helper, HelperResult, Output};
 use sass_rs::{compile_file, Options};
 use serde_derive::Serialize;
 use serde_json::json;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::AsRef;
 use std::error::Error;
 use std::fs::{self, File};
 use std::io::{self, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+static FEED_PATHS: &[&str] = &["feed.xml", "atom.xml"];
+static JSON_FEED_PATH: &str = "feed.json";
+/// The latest posts of every blog together.
+static COMBINED_FEED_PATH: &str = "all.xml";
+static COMBINED_FEED_LENGTH: usize = 20;
+/// The feeds of every blog, to subscribe to all of them at once.
+static OPML_FILE: &str = "feeds.opml";
+
+static SEARCH_INDEX_FILE: &str = "search-index.json";
+static REDIRECTS_FILE: &str = "_redirects";
+static NOT_FOUND_FILE: &str = "404.html";
+static DENY_BROKEN_LINKS_VAR: &str = "DENY_BROKEN_LINKS";
+static DENY_FUTURE_POSTS_VAR: &str = "DENY_FUTURE_POSTS";
+static WARN_DEPRECATED_FIELDS_VAR: &str = "WARN_DEPRECATED_FIELDS";
+static STAGING_VAR: &str = "STAGING";
+static EXCLUDE_DIRS_VAR: &str = "EXCLUDE_DIRS";
+static DIAGNOSTICS_FILE_VAR: &str = "DIAGNOSTICS_FILE";
+static SOURCE_DATE_EPOCH_VAR: &str = "SOURCE_DATE_EPOCH";
+
+/// Number of related posts listed below a post.
+static RELATED_POSTS: usize = 3;
+
+/// Rewritten after every build while watching, for the pages to notice and
+/// reload themselves.
+static LIVE_RELOAD_FILE: &str = "livereload.txt";
+static LIVE_RELOAD_SCRIPT: &str = r#"<script>
+  // reload the page whenever `--watch` regenerates the site
+  (function () {
+    var version = null;
+    setInterval(function () {
+      fetch("/livereload.txt", { cache: "no-store" })
+        .then(function (response) { return response.ok ? response.text() : null; })
+        .then(function (current) {
+          if (version !== null && current !== null && current !== version) {
+            location.reload();
+          }
+          version = current;
+        })
+        .catch(function () {});
+    }, 1000);
+  })();
+</script>"#;
+
+/// The assets copied to the site, which posts link images from.
+static STATIC_DIRECTORY: &str = "static";
+/// The directories of `STATIC_DIRECTORY` copied to the root of the site.
+static STATIC_SUBDIRECTORIES: &[&str] = &["fonts", "images", "styles", "scripts"];
+
+/// What builds keep for the next ones, which isn't part of the site.
+static CACHE_DIRECTORY: &str = ".cache";
+static CACHE_FILE: &str = "posts.json";
+/// The directory of the cache recording what was generated in each output
+/// directory, see `output_records`.
+static OUTPUTS_DIRECTORY: &str = "outputs";
+/// The files generated by the last run, relative to the output directory, so
+/// the next one can remove those it doesn't generate anymore.
+static GENERATED_LIST_FILE: &str = "generated";
+/// The hashes of the contents of the files generated by the last run, so the
+/// next one can tell which changed.
+static OUTPUT_HASHES_FILE: &str = "hashes";
+static WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 struct Generator<'a> {
     handlebars: Handlebars<'a>,
+    config: Config,
     blogs: Vec<Blog>,
     out_directory: PathBuf,
+    posts_directory: PathBuf,
+    exclude: Vec<Pattern>,
+    cache_directory: PathBuf,
+    cache: PostCache,
+    /// Where the generated files of the previous run are listed, along with
+    /// their hashes.
+    output_records: PathBuf,
+    /// Every file written to the output directory, relative to it.
+    written: RefCell<BTreeSet<PathBuf>>,
+    /// The files each blog was last rendered to, by prefix, so reloading it
+    /// can tell which it doesn't generate anymore.
+    blog_files: RefCell<HashMap<PathBuf, BTreeSet<PathBuf>>>,
+    /// The hashes of the files written by the previous run, by path.
+    previous_hashes: HashMap<PathBuf, String>,
+    /// The hashes of the files written by this run, by path.
+    hashes: RefCell<BTreeMap<PathBuf, String>>,
+    live_reload: bool,
+    /// Whether posts dated in the future fail the build, see `check_dates`.
+    deny_future_posts: bool,
+    diagnostics: Diagnostics,
+    /// Where the problems found are written as JSON, if anywhere.
+    diagnostics_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Serialize)]
@@ -31,6 +139,37 @@ struct ReleasePost {
     title: String,
     url: String,
 }
+
+#[derive(Debug, Serialize)]
+struct PostsIndex {
+    posts: Vec<IndexedPost>,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexedPost {
+    title: String,
+    url: String,
+    date: String,
+    authors: Vec<String>,
+    tags: Vec<String>,
+    lang: String,
+    excerpt: String,
+    word_count: usize,
+    heading_count: usize,
+    /// The source file of the post, relative to the root of the repository.
+    source_path: String,
+}
+
+/// A post as a document for client-side search libraries like lunr.
+#[derive(Debug, Serialize)]
+struct SearchDocument {
+    id: String,
+    title: String,
+    url: String,
+    tags: Vec<String>,
+    body: String,
+}
+
 handlebars_helper!(hb_month_name_helper: |month_num: u64| match month_num {
     1 => "Jan.",
     2 => "Feb.",
@@ -49,21 +188,87 @@ handlebars_helper!(hb_month_name_helper: |month_num: u64| match month_num {
 
 impl<'a> Generator<'a> {
     fn new(
+        config: Config,
         out_directory: impl AsRef<Path>,
         posts_directory: impl AsRef<Path>,
+        cache_directory: impl AsRef<Path>,
     ) -> Result<Self, Box<dyn Error>> {
         let mut handlebars = Handlebars::new();
         handlebars.set_strict_mode(true);
         handlebars.register_templates_directory(".hbs", "templates")?;
         handlebars.register_helper("month_name", Box::new(hb_month_name_helper));
+        handlebars.register_partial("live_reload", "")?;
+        let base_url = config.base_url.clone();
+        handlebars.register_helper(
+            "base_url",
+            Box::new(
+                move |_: &Helper,
+                      _: &Handlebars,
+                      _: &Context,
+                      _: &mut handlebars::RenderContext,
+                      out: &mut dyn Output|
+                      -> HelperResult {
+                    out.write(&base_url)?;
+                    Ok(())
+                },
+            ),
+        );
+
+        let cache_directory = cache_directory.as_ref();
+        let cache = PostCache::load(&cache_directory.join(CACHE_FILE));
+        let output_records = output_records(cache_directory, out_directory.as_ref())?;
+
+        // e.g. `EXCLUDE_DIRS=drafts,_*` to skip directories which aren't blogs
+        let mut exclude = Vec::new();
+        if let Some(patterns) = std::env::var_os(EXCLUDE_DIRS_VAR) {
+            for pattern in patterns.to_string_lossy().split(',') {
+                exclude.push(Pattern::new(pattern.trim()).map_err(|e| {
+                    format!(
+                        "invalid pattern `{}` in {}: {}",
+                        pattern, EXCLUDE_DIRS_VAR, e
+                    )
+                })?);
+            }
+        }
 
         Ok(Generator {
             handlebars,
-            blogs: self::blogs::load(posts_directory.as_ref())?,
+            blogs: self::blogs::load(posts_directory.as_ref(), &exclude, &cache, &config)?,
+            config,
             out_directory: out_directory.as_ref().into(),
+            posts_directory: posts_directory.as_ref().into(),
+            exclude,
+            cache_directory: cache_directory.to_path_buf(),
+            cache,
+            written: RefCell::new(BTreeSet::new()),
+            blog_files: RefCell::new(HashMap::new()),
+            previous_hashes: load_output_hashes(&output_records)?,
+            output_records,
+            hashes: RefCell::new(BTreeMap::new()),
+            live_reload: false,
+            deny_future_posts: false,
+            diagnostics: Diagnostics::default(),
+            diagnostics_file: None,
         })
     }
 
+    /// Make the pages reload themselves after each build, for watch mode.
+    fn enable_live_reload(&mut self) -> Result<(), Box<dyn Error>> {
+        self.handlebars
+            .register_partial("live_reload", LIVE_RELOAD_SCRIPT)?;
+        self.live_reload = true;
+        Ok(())
+    }
+
+    /// Tell the pages open in browsers that the site changed.
+    fn signal_live_reload(&self) -> Result<(), Box<dyn Error>> {
+        if self.live_reload {
+            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+            self.write_file(LIVE_RELOAD_FILE, now.as_millis().to_string())?;
+        }
+        Ok(())
+    }
+
     fn file_url(&self, path: &Path) -> String {
         format!(
             "file:///{}/{}",
@@ -80,17 +285,183 @@ impl<'a> Generator<'a> {
         .replace(std::path::MAIN_SEPARATOR, "/")
     }
 
+    /// Generate the whole site. The problems found along the way are also
+    /// written as JSON to the `diagnostics_file`, even if they failed the
+    /// build.
     fn render(&self) -> Result<(), Box<dyn Error>> {
+        let result = self.render_site();
+        self.save_diagnostics()?;
+        result
+    }
+
+    fn save_diagnostics(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(path) = &self.diagnostics_file {
+            self.diagnostics.save(path)?;
+        }
+        Ok(())
+    }
+
+    fn render_site(&self) -> Result<(), Box<dyn Error>> {
+        self.check_dates()?;
+        self.check_front_matter();
+
         // make sure our output directory exists
         fs::create_dir_all(&self.out_directory)?;
 
-        for blog in &self.blogs {
-            self.render_blog(blog)?;
-        }
+        self.render_pages()?;
         self.compile_sass("app");
         self.compile_sass("fonts");
         self.concat_vendor_css(vec!["skeleton", "tachyons"]);
         self.copy_static_files()?;
+        self.signal_live_reload()?;
+        self.cache.save(&self.cache_directory.join(CACHE_FILE))?;
+        self.check_links(|target| {
+            let path = self.out_directory.join(target);
+            path.is_file() || path.join("index.html").is_file()
+        })
+    }
+
+    /// Generate every page of the site, leaving out the styles and the other
+    /// static files.
+    fn render_pages(&self) -> Result<(), Box<dyn Error>> {
+        for blog in &self.blogs {
+            self.render_blog(blog)?;
+        }
+        self.render_sitemap()?;
+        self.render_combined_feed()?;
+        self.render_opml()?;
+        self.render_robots()?;
+        self.render_not_found()?;
+        self.render_posts_index()?;
+        self.render_search_index()?;
+        self.render_redirects()
+    }
+
+    /// Everything `render` checks, without generating the site. Only links to
+    /// posts and to static files are checked, as the other pages, like feeds
+    /// and tag pages, would have to be generated to know they exist.
+    fn check(&self) -> Result<(), Box<dyn Error>> {
+        let result = self.check_site();
+        self.save_diagnostics()?;
+        result
+    }
+
+    fn check_site(&self) -> Result<(), Box<dyn Error>> {
+        self.check_dates()?;
+        self.check_front_matter();
+        for blog in &self.blogs {
+            for post in blog.posts() {
+                self.check_layout(post)?;
+            }
+        }
+        self.check_links(|target| {
+            let is_static = STATIC_SUBDIRECTORIES
+                .iter()
+                .any(|dir| target.starts_with(&format!("{}/", dir)));
+            if is_static {
+                Path::new(STATIC_DIRECTORY).join(target).is_file()
+            } else {
+                // the posts which exist are already known, so only links
+                // which could be to other pages are left
+                !self.blogs.iter().any(|blog| blog.is_post_file(target))
+            }
+        })
+    }
+
+    /// Fail if anything was reported as a warning, for `--deny-warnings`. It's
+    /// checked once the whole site was generated, so that every warning is
+    /// reported at once.
+    fn deny_warnings(&self) -> Result<(), Box<dyn Error>> {
+        let warnings = self.diagnostics.count(Severity::Warning);
+        if warnings > 0 {
+            return Err(format!("found {} warning(s), denied by --deny-warnings", warnings).into());
+        }
+        Ok(())
+    }
+
+    /// Reject posts dated in the future, which are usually a typo in the year,
+    /// if `deny_future_posts` is set. It's off by default, as some blogs
+    /// schedule posts on purpose.
+    fn check_dates(&self) -> Result<(), Box<dyn Error>> {
+        if !self.deny_future_posts {
+            return Ok(());
+        }
+        let today = chrono::Utc::today().naive_utc();
+        let mut future = 0;
+        for post in self.blogs.iter().flat_map(|blog| blog.future_posts(today)) {
+            self.diagnostics.report(
+                "future-post",
+                Severity::Error,
+                Some(&post.path),
+                format!("post dated in the future: `{}`", post.path.display()),
+            );
+            future += 1;
+        }
+        if future > 0 {
+            return Err(format!("found {} post(s) dated in the future", future).into());
+        }
+        Ok(())
+    }
+
+    /// Warn about posts using the old names of renamed front matter fields, if
+    /// the `WARN_DEPRECATED_FIELDS` environment variable is set.
+    fn check_front_matter(&self) {
+        if std::env::var_os(WARN_DEPRECATED_FIELDS_VAR).is_none() {
+            return;
+        }
+        for post in self.blogs.iter().flat_map(|blog| blog.posts()) {
+            for (old, new) in &post.deprecated_fields {
+                self.diagnostics.report(
+                    "deprecated-field",
+                    Severity::Warning,
+                    Some(&post.path),
+                    format!(
+                        "deprecated front matter field `{}` in `{}`, use `{}` instead",
+                        old,
+                        post.path.display(),
+                        new
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Report links between posts that don't lead anywhere, and images that
+    /// are missing. They are only warnings, unless the `DENY_BROKEN_LINKS`
+    /// environment variable is set.
+    fn check_links(&self, exists: impl Fn(&str) -> bool) -> Result<(), Box<dyn Error>> {
+        let deny = std::env::var_os(DENY_BROKEN_LINKS_VAR).is_some();
+        let severity = if deny {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        let mut broken = 0;
+        for link in links::find_broken(&self.blogs, exists) {
+            self.diagnostics.report(
+                "broken-link",
+                severity,
+                Some(&link.path),
+                format!("broken link in {}: {}", link.post, link.link),
+            );
+            broken += 1;
+        }
+        for image in links::find_missing_images(&self.blogs, Path::new(STATIC_DIRECTORY)) {
+            self.diagnostics.report(
+                "missing-image",
+                severity,
+                Some(&image.post),
+                format!(
+                    "missing image in {}: {}",
+                    image.post.display(),
+                    image.file.display()
+                ),
+            );
+            broken += 1;
+        }
+        if deny && broken > 0 {
+            return Err(format!("found {} broken link(s) or image(s)", broken).into());
+        }
         Ok(())
     }
 
@@ -99,11 +470,11 @@ impl<'a> Generator<'a> {
         let css_file = format!("./static/styles/{}.css", filename);
 
         let css = compile_file(&scss_file, Options::default())
-            .expect(&format!("couldn't compile sass: {}", &scss_file));
-        let mut file =
-            File::create(&css_file).expect(&format!("couldn't make css file: {}", &css_file));
+            .unwrap_or_else(|_| panic!("couldn't compile sass: {}", &scss_file));
+        let mut file = File::create(&css_file)
+            .unwrap_or_else(|_| panic!("couldn't make css file: {}", &css_file));
         file.write_all(&css.into_bytes())
-            .expect(&format!("couldn't write css file: {}", &css_file));
+            .unwrap_or_else(|_| panic!("couldn't write css file: {}", &css_file));
     }
 
     fn concat_vendor_css(&self, files: Vec<&str>) {
@@ -116,15 +487,84 @@ impl<'a> Generator<'a> {
         fs::write("./static/styles/vendor.css", &concatted).expect("couldn't write vendor css");
     }
 
+    /// Render every page of a blog. The files it rendered last time but not
+    /// anymore, like the pages of removed posts, are forgotten, so
+    /// `remove_stale` removes them.
     fn render_blog(&self, blog: &Blog) -> Result<(), Box<dyn Error>> {
+        let written = self.written.replace(BTreeSet::new());
+        let result = self.render_blog_files(blog);
+        let files = self.written.replace(written);
+        self.written.borrow_mut().extend(files.iter().cloned());
+        result?;
+
+        let previous = self
+            .blog_files
+            .borrow_mut()
+            .insert(blog.prefix().to_path_buf(), files)
+            .unwrap_or_default();
+        self.forget_files(previous);
+        Ok(())
+    }
+
+    /// Forget about the files of blogs which aren't there anymore.
+    fn forget_removed_blogs(&self) {
+        let removed: Vec<_> = self
+            .blog_files
+            .borrow()
+            .keys()
+            .filter(|prefix| !self.blogs.iter().any(|blog| blog.prefix() == *prefix))
+            .cloned()
+            .collect();
+        for prefix in removed {
+            let files = self.blog_files.borrow_mut().remove(&prefix);
+            self.forget_files(files.unwrap_or_default());
+        }
+    }
+
+    /// Stop counting the given files as generated, unless a blog still renders
+    /// them.
+    fn forget_files(&self, files: BTreeSet<PathBuf>) {
+        let blog_files = self.blog_files.borrow();
+        for file in files {
+            if !blog_files.values().any(|files| files.contains(&file)) {
+                self.written.borrow_mut().remove(&file);
+                self.hashes.borrow_mut().remove(&file);
+            }
+        }
+    }
+
+    fn render_blog_files(&self, blog: &Blog) -> Result<(), Box<dyn Error>> {
         std::fs::create_dir_all(self.out_directory.join(blog.prefix()))?;
 
         let path = self.render_index(blog)?;
 
         println!("{}: {}", blog.title(), self.file_url(&path));
 
-        self.render_feed(blog)?;
+        if blog.generate_feed() {
+            self.render_feed(blog)?;
+            self.render_json_feed(blog)?;
+        }
         self.render_releases_feed(blog)?;
+        self.copy_extra_styles(blog)?;
+
+        for (tag, posts) in blog.posts_by_tag() {
+            self.render_tag_index(blog, tag, &posts)?;
+        }
+
+        for author in blog.posts_by_author().values() {
+            self.render_author_index(blog, author)?;
+        }
+
+        for (year, posts) in blog.posts_by_year() {
+            self.render_year_archive(blog, year, posts)?;
+        }
+
+        let languages = blog.languages();
+        if languages.len() > 1 {
+            for lang in languages {
+                self.render_lang_index(blog, lang)?;
+            }
+        }
 
         for (i, post) in blog.posts().iter().enumerate() {
             let path = self.render_post(blog, post)?;
@@ -136,10 +576,14 @@ impl<'a> Generator<'a> {
         Ok(())
     }
 
+    /// The blogs included in listings spanning every blog.
+    fn listed_blogs(&self) -> impl Iterator<Item = &Blog> {
+        self.blogs.iter().filter(|blog| !blog.unlisted())
+    }
+
     fn render_index(&self, blog: &Blog) -> Result<PathBuf, Box<dyn Error>> {
         let other_blogs: Vec<_> = self
-            .blogs
-            .iter()
+            .listed_blogs()
             .filter(|b| b.index_title() != blog.index_title())
             .map(|other_blog| {
                 json!({
@@ -149,57 +593,215 @@ impl<'a> Generator<'a> {
             })
             .collect();
 
+        let page_size = blog.page_size().unwrap_or(blog.posts().len());
+        for page in blog.pages(page_size) {
+            // pages after the first one live two directories below the index
+            let index_root = if page.number == 1 { "" } else { "../../" };
+            let path = blog.prefix().join(&page.path);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(self.out_directory.join(dir))?;
+            }
+
+            let data = json!({
+                "title": blog.index_title(),
+                "parent": "layout",
+                "blog": blog,
+                "other_blogs": other_blogs,
+                "page": page,
+                "featured": if page.number == 1 { blog.featured_posts() } else { Vec::new() },
+                "active_since": blog.first_post_date().map(|date| date.year()),
+                "last_post_date": blog.last_post_date(),
+                "index_root": index_root,
+                "root": blog.path_back_to_root().join(index_root),
+            });
+            self.render_template(&path, "index", data)?;
+        }
+        Ok(blog.prefix().join("index.html"))
+    }
+
+    fn render_year_archive(
+        &self,
+        blog: &Blog,
+        year: i32,
+        posts: &[Post],
+    ) -> Result<(), Box<dyn Error>> {
+        let path = blog.prefix().join("archive").join(format!("{:04}", year));
+        fs::create_dir_all(self.out_directory.join(&path))?;
+
         let data = json!({
-            "title": blog.index_title(),
+            "title": format!("Posts in {} | {}", year, blog.title()),
             "parent": "layout",
             "blog": blog,
-            "other_blogs": other_blogs,
-            "root": blog.path_back_to_root(),
+            "heading": format!("Posts in {}.", year),
+            "posts": posts,
+            "root": blog.path_back_to_root().join("../../"),
         });
-        let path = blog.prefix().join("index.html");
-        self.render_template(&path, "index", data)?;
-        Ok(path)
+        self.render_template(path.join("index.html"), "listing", data)?;
+        Ok(())
     }
 
-    fn render_post(&self, blog: &Blog, post: &Post) -> Result<PathBuf, Box<dyn Error>> {
-        let path = blog
-            .prefix()
-            .join(format!("{:04}", &post.year))
-            .join(format!("{:02}", &post.month))
-            .join(format!("{:02}", &post.day));
+    fn render_lang_index(&self, blog: &Blog, lang: &str) -> Result<(), Box<dyn Error>> {
+        let path = blog.prefix().join("lang").join(slugify(lang));
+        fs::create_dir_all(self.out_directory.join(&path))?;
+
+        let data = json!({
+            "title": format!("Posts in {} | {}", lang, blog.title()),
+            "parent": "layout",
+            "blog": blog,
+            "heading": format!("Posts in \"{}\".", lang),
+            "posts": blog.posts_in_lang(lang),
+            "root": blog.path_back_to_root().join("../../"),
+        });
+        self.render_template(path.join("index.html"), "listing", data)?;
+        Ok(())
+    }
+
+    fn render_author_index(&self, blog: &Blog, author: &AuthorPosts) -> Result<(), Box<dyn Error>> {
+        let path = blog.prefix().join("authors").join(slugify(author.name));
+        fs::create_dir_all(self.out_directory.join(&path))?;
+
+        let data = json!({
+            "title": format!("Posts by {} | {}", author.name, blog.title()),
+            "parent": "layout",
+            "blog": blog,
+            "heading": format!("Posts by {}.", author.name),
+            "posts": author.posts,
+            "root": blog.path_back_to_root().join("../../"),
+        });
+        self.render_template(path.join("index.html"), "listing", data)?;
+        Ok(())
+    }
+
+    fn render_tag_index(
+        &self,
+        blog: &Blog,
+        tag: &str,
+        posts: &[&Post],
+    ) -> Result<(), Box<dyn Error>> {
+        let tag_dir = Path::new("tags").join(slugify(tag));
+        let path = blog.prefix().join(&tag_dir);
         fs::create_dir_all(self.out_directory.join(&path))?;
 
-        // then, we render the page in that path
-        let mut filename = PathBuf::from(&post.filename);
-        filename.set_extension("html");
+        let data = json!({
+            "title": format!("Posts tagged {} | {}", tag, blog.title()),
+            "parent": "layout",
+            "blog": blog,
+            "heading": format!("Posts tagged \"{}\".", tag),
+            "posts": posts,
+            "root": blog.path_back_to_root().join("../../"),
+        });
+        self.render_template(path.join("index.html"), "listing", data)?;
+        if !blog.generate_feed() {
+            return Ok(());
+        }
+
+        // Posts are already in the same order as in the main feed.
+        let feed_path = tag_dir.join("feed.xml");
+        let data = json!({
+            "blog": blog,
+            "posts": feed_posts(blog, posts.iter().copied())?,
+            "feed_title": format!("{} – {}", blog.title(), tag),
+            "feed_home": format!("{}/", tag_dir.display()),
+            "feed_path": feed_path,
+            "feed_updated": feed_updated(blog, posts.iter().copied()),
+        });
+        self.render_template(blog.prefix().join(feed_path), "feed", data)?;
+        Ok(())
+    }
+
+    fn check_layout(&self, post: &Post) -> Result<(), Box<dyn Error>> {
+        if !self.handlebars.has_template(&post.layout) {
+            return Err(format!(
+                "blog post at path `{}` has an unknown layout `{}`",
+                post.path.display(),
+                post.layout
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn render_post(&self, blog: &Blog, post: &Post) -> Result<PathBuf, Box<dyn Error>> {
+        self.check_layout(post)?;
+        let path = post_file(blog, post);
+        fs::create_dir_all(self.out_directory.join(path.parent().unwrap()))?;
+
+        let root = blog
+            .path_back_to_root()
+            .join("../".repeat(post.url.matches('/').count()));
+        let mut post_data = serde_json::to_value(post)?;
+        let source = format!("{}{}", blog.url_prefix(), post.url);
+        let mut contents = post.contents.clone();
+        if blog.image_dimensions() {
+            contents = links::add_image_dimensions(
+                &contents,
+                blog.base_url(),
+                &source,
+                Path::new(STATIC_DIRECTORY),
+            );
+        }
+        if blog.asset_hashes() {
+            contents = links::add_asset_hashes(
+                &contents,
+                blog.base_url(),
+                &source,
+                Path::new(STATIC_DIRECTORY),
+            );
+        }
+        post_data["contents"] = links::make_relative(&contents, &root.to_string_lossy()).into();
 
+        let translations = blog.translations(post);
         let data = json!({
             "title": format!("{} | {}", post.title, blog.title()),
             "parent": "layout",
             "blog": blog,
-            "post": post,
-            "root": blog.path_back_to_root().join("../../../"),
+            "post": post_data,
+            "translations": translations
+                .iter()
+                .map(|(lang, url)| json!({ "lang": lang, "url": url }))
+                .collect::<Vec<_>>(),
+            "related": blog.related_posts(post, RELATED_POSTS),
+            "hreflang": hreflang_links(blog, post, &translations),
+            "json_ld": article_json_ld(blog, post),
+            "root": root,
         });
 
-        let path = path.join(filename);
         self.render_template(&path, &post.layout, data)?;
         Ok(path)
     }
 
     fn render_feed(&self, blog: &Blog) -> Result<(), Box<dyn Error>> {
-        let posts: Vec<_> = blog.posts().iter().take(10).collect();
-        let data = json!({
-            "blog": blog,
-            "posts": posts,
-            "feed_updated": chrono::Utc::now().with_nanosecond(0).unwrap().to_rfc3339(),
-        });
+        let posts = feed_posts(blog, blog.posts())?;
+        let feed_updated = feed_updated(blog, blog.posts());
+
+        // The feed has always been Atom, but it lives at `feed.xml` for
+        // historical reasons. Also publish it under the conventional name.
+        for feed_path in FEED_PATHS {
+            let data = json!({
+                "blog": blog,
+                "posts": posts,
+                "feed_title": blog.title(),
+                "feed_home": "",
+                "feed_path": feed_path,
+                "feed_updated": feed_updated,
+            });
+
+            self.render_template(blog.prefix().join(feed_path), "feed", data)?;
+        }
+        Ok(())
+    }
 
-        self.render_template(blog.prefix().join("feed.xml"), "feed", data)?;
+    fn render_json_feed(&self, blog: &Blog) -> Result<(), Box<dyn Error>> {
+        let feed = JsonFeed::new(blog, JSON_FEED_PATH, blog.feed_length());
+        self.write_file(
+            blog.prefix().join(JSON_FEED_PATH),
+            serde_json::to_string(&feed)?,
+        )?;
         Ok(())
     }
 
     fn render_releases_feed(&self, blog: &Blog) -> Result<(), Box<dyn Error>> {
-        let posts = blog.posts().iter().cloned().collect::<Vec<_>>();
+        let posts = blog.posts().to_vec();
         let is_released: Vec<&Post> = posts.iter().filter(|post| post.release).collect();
         let releases: Vec<ReleasePost> = is_released
             .iter()
@@ -212,62 +814,2273 @@ impl<'a> Generator<'a> {
                     .to_string(),
             })
             .collect();
+        // the latest release, or else the latest post for blogs without any
+        let feed_updated = is_released
+            .iter()
+            .map(|post| post.updated.clone())
+            .max()
+            .unwrap_or_else(|| feed_updated(blog, blog.posts()));
         let data = Releases {
             releases,
-            feed_updated: chrono::Utc::now().with_nanosecond(0).unwrap().to_rfc3339(),
+            feed_updated,
         };
-        fs::write(
-            self.out_directory.join(blog.prefix()).join("releases.json"),
+        self.write_file(
+            blog.prefix().join("releases.json"),
             serde_json::to_string(&data)?,
         )?;
         Ok(())
     }
 
-    fn copy_static_files(&self) -> Result<(), Box<dyn Error>> {
-        copy_dir("static/fonts", &self.out_directory)?;
-        copy_dir("static/images", &self.out_directory)?;
-        copy_dir("static/styles", &self.out_directory)?;
-        copy_dir("static/scripts", &self.out_directory)?;
+    fn render_sitemap(&self) -> Result<(), Box<dyn Error>> {
+        let mut urls = Vec::new();
+        for blog in self.listed_blogs() {
+            urls.push(json!({
+                "loc": blog.absolute_url(""),
+                "lastmod": blog.posts().first().map(|post| post.updated_on.to_string()),
+            }));
+            for post in blog.posts().iter().filter(|post| !post.noindex) {
+                urls.push(json!({
+                    "loc": blog.absolute_url(&post.url),
+                    "lastmod": post.updated_on.to_string(),
+                }));
+            }
+        }
+
+        self.render_template("sitemap.xml", "sitemap", json!({ "urls": urls }))?;
         Ok(())
     }
 
-    fn render_template(
-        &self,
-        name: impl AsRef<Path>,
-        template: &str,
-        data: serde_json::Value,
-    ) -> Result<(), Box<dyn Error>> {
-        let out_file = self.out_directory.join(name.as_ref());
-        let file = File::create(out_file)?;
-        self.handlebars.render_to_write(template, &data, file)?;
+    fn render_combined_feed(&self) -> Result<(), Box<dyn Error>> {
+        let blogs = self.listed_blogs().filter(|blog| blog.generate_feed());
+        let posts = combined_feed_posts(blogs, COMBINED_FEED_LENGTH)?;
+        let feed_updated = posts
+            .iter()
+            .filter_map(|post| post["updated"].as_str())
+            .max()
+            .map(str::to_string)
+            .unwrap_or_else(build_time);
+        let data = json!({
+            "posts": posts,
+            "feed_path": COMBINED_FEED_PATH,
+            "feed_updated": feed_updated,
+        });
+        self.render_template(COMBINED_FEED_PATH, "combined_feed", data)?;
         Ok(())
     }
-}
 
-fn copy_dir(source: impl AsRef<Path>, dest: impl AsRef<Path>) -> Result<(), io::Error> {
-    let source = source.as_ref();
-    let dest = dest.as_ref().join(source.file_name().unwrap());
-    assert!(source.is_dir());
-    fn copy_inner(source: &Path, dest: &Path) -> Result<(), io::Error> {
-        fs::create_dir_all(dest)?;
-        for entry in fs::read_dir(source)? {
-            let entry = entry?;
-            let new_dest = dest.join(entry.file_name());
-            if entry.file_type()?.is_dir() {
-                copy_inner(&entry.path(), &new_dest)?;
-            } else {
-                fs::copy(&entry.path(), &new_dest)?;
+    fn render_opml(&self) -> Result<(), Box<dyn Error>> {
+        let blogs: Vec<_> = self
+            .listed_blogs()
+            .filter(|blog| blog.generate_feed())
+            .map(|blog| {
+                json!({
+                    "title": blog.title(),
+                    "url": blog.absolute_url(""),
+                    "feed_url": blog.absolute_url(FEED_PATHS[0]),
+                })
+            })
+            .collect();
+
+        self.render_template(OPML_FILE, "opml", json!({ "blogs": blogs }))?;
+        Ok(())
+    }
+
+    /// The page hosts serve for urls which don't lead anywhere. It's served at
+    /// any depth, so its links are relative to the root of the host instead of
+    /// to the page.
+    fn render_not_found(&self) -> Result<(), Box<dyn Error>> {
+        let blog = match self
+            .blogs
+            .iter()
+            .find(|blog| blog.prefix().as_os_str().is_empty())
+            .or_else(|| self.blogs.first())
+        {
+            Some(blog) => blog,
+            None => return Ok(()),
+        };
+        let root = match self.config.base_url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+            None => "/",
+        };
+        let data = json!({
+            "title": format!("Page not found | {}", blog.title()),
+            "parent": "layout",
+            "blog": blog,
+            "message": blog.not_found_html(),
+            "root": root,
+        });
+        self.render_template(NOT_FOUND_FILE, "404", data)?;
+        Ok(())
+    }
+
+    /// Point crawlers at the sitemap. Staging builds, marked by the `STAGING`
+    /// environment variable, keep all crawlers out instead.
+    fn render_robots(&self) -> Result<(), Box<dyn Error>> {
+        let rule = if std::env::var_os(STAGING_VAR).is_some() {
+            "Disallow: /"
+        } else {
+            "Allow: /"
+        };
+        self.write_file(
+            "robots.txt",
+            format!(
+                "User-agent: *\n{}\n\nSitemap: {}sitemap.xml\n",
+                rule, self.config.base_url
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn render_posts_index(&self) -> Result<(), Box<dyn Error>> {
+        let mut posts: Vec<IndexedPost> = self
+            .listed_blogs()
+            .flat_map(|blog| {
+                blog.posts().iter().map(move |post| IndexedPost {
+                    title: post.title.clone(),
+                    url: format!("{}{}", blog.url_prefix(), post.url),
+                    date: format!("{:04}-{:02}-{:02}", post.year, post.month, post.day),
+                    authors: post.authors.clone(),
+                    tags: post.tags.clone(),
+                    lang: post.lang.clone(),
+                    excerpt: post.description.clone(),
+                    word_count: post.word_count(),
+                    heading_count: post.heading_count(),
+                    source_path: post.source_path.clone(),
+                })
+            })
+            .collect();
+        // Newest first, with a stable order between builds.
+        posts.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.url.cmp(&b.url)));
+
+        self.write_file(
+            "posts.json",
+            serde_json::to_string_pretty(&PostsIndex { posts })?,
+        )?;
+        Ok(())
+    }
+
+    /// Write the documents to build a search index of every post from. The
+    /// url of a post doubles as its id.
+    fn render_search_index(&self) -> Result<(), Box<dyn Error>> {
+        let mut documents: Vec<SearchDocument> = self
+            .listed_blogs()
+            .flat_map(|blog| {
+                blog.posts().iter().map(move |post| {
+                    let url = format!("{}{}", blog.url_prefix(), post.url);
+                    SearchDocument {
+                        id: url.clone(),
+                        title: post.title.clone(),
+                        url,
+                        tags: post.tags.clone(),
+                        body: post.plain_text().to_string(),
+                    }
+                })
+            })
+            .collect();
+        // The same order on every build.
+        documents.sort_by(|a, b| a.id.cmp(&b.id));
+
+        self.write_file(SEARCH_INDEX_FILE, serde_json::to_string(&documents)?)?;
+        Ok(())
+    }
+
+    /// Write the redirects of moved posts in the `_redirects` format, one
+    /// `<old path> <new path> 301` rule per line.
+    fn render_redirects(&self) -> Result<(), Box<dyn Error>> {
+        let mut rules = String::new();
+        for (source, target) in blogs::redirects(&self.blogs)? {
+            rules.push_str(&format!("{} {} 301\n", source, target));
+        }
+        self.write_file(REDIRECTS_FILE, rules)?;
+        Ok(())
+    }
+
+    fn copy_extra_styles(&self, blog: &Blog) -> Result<(), Box<dyn Error>> {
+        for style in blog.extra_styles() {
+            let source = blog.dir().join(style);
+            let contents = fs::read(&source)
+                .map_err(|e| format!("couldn't read stylesheet {}: {}", source.display(), e))?;
+            let path = blog.prefix().join(style);
+            fs::create_dir_all(self.out_directory.join(path.parent().unwrap()))?;
+            self.write_file(path, contents)?;
+        }
+        Ok(())
+    }
+
+    fn copy_static_files(&self) -> Result<(), Box<dyn Error>> {
+        for dir in STATIC_SUBDIRECTORIES {
+            for file in copy_dir(Path::new(STATIC_DIRECTORY).join(dir), &self.out_directory)? {
+                let file = file.strip_prefix(&self.out_directory)?;
+                self.written.borrow_mut().insert(file.to_path_buf());
             }
         }
         Ok(())
     }
-    copy_inner(source, &dest)
-}
 
-pub fn main() -> Result<(), Box<dyn Error>> {
-    let blog = Generator::new("site", "posts")?;
+    /// Remove the files the previous run generated but this one didn't, like
+    /// the pages of deleted posts, along with the directories left empty. With
+    /// `dry_run`, they are only listed.
+    fn remove_stale(&self, dry_run: bool) -> Result<(), Box<dyn Error>> {
+        let list = self.output_records.join(GENERATED_LIST_FILE);
+        let previous = match fs::read_to_string(&list) {
+            Ok(previous) => previous,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
 
-    blog.render()?;
+        let mut kept = Vec::new();
+        for line in previous.lines() {
+            let file = Path::new(line);
+            // never touch anything outside of the output directory
+            let is_inside = file.components().all(|c| matches!(c, Component::Normal(_)));
+            if line.is_empty() || !is_inside || self.written.borrow().contains(file) {
+                continue;
+            }
+            let path = self.out_directory.join(file);
+            if dry_run {
+                println!("would remove stale file {}", path.display());
+                kept.push(file.to_path_buf());
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => println!("removed stale file {}", path.display()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("couldn't remove {}: {}", path.display(), e).into()),
+            }
+            for dir in path.ancestors().skip(1) {
+                if dir == self.out_directory || fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+        // Files a dry run would have removed are still around for the next run.
+        self.save_generated_list(&kept)
+    }
 
-    Ok(())
+    fn save_generated_list(&self, extra: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        let mut list = String::new();
+        for file in self.written.borrow().iter().chain(extra) {
+            list.push_str(&file.to_string_lossy());
+            list.push('\n');
+        }
+        fs::create_dir_all(&self.output_records)?;
+        fs::write(self.output_records.join(GENERATED_LIST_FILE), list)?;
+
+        let mut hashes = String::new();
+        for (file, hash) in self.hashes.borrow().iter() {
+            hashes.push_str(&format!("{} {}\n", hash, file.to_string_lossy()));
+        }
+        fs::write(self.output_records.join(OUTPUT_HASHES_FILE), hashes)?;
+        Ok(())
+    }
+
+    /// The urls of the posts whose page isn't the same as the one the previous
+    /// run generated, including new posts.
+    fn changed_posts(&self) -> Vec<String> {
+        let hashes = self.hashes.borrow();
+        let mut changed = Vec::new();
+        for blog in &self.blogs {
+            for post in blog.posts() {
+                let file = post_file(blog, post);
+                if hashes.get(&file) != self.previous_hashes.get(&file) {
+                    changed.push(blog.absolute_url(&post.url));
+                }
+            }
+        }
+        changed
+    }
+
+    /// Poll the posts directory forever, reloading and rendering again the
+    /// blogs whose files changed.
+    ///
+    /// Comparing the modification times of the few hundred files every
+    /// `WATCH_INTERVAL` is cheap, and unlike filesystem events it works the
+    /// same everywhere, including on the network and container mounts which
+    /// don't deliver them. Editors saving through a temporary file just show up
+    /// as a changed file in the next snapshot.
+    fn watch(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut snapshot = modified_times(&self.posts_directory)?;
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            // Keep watching, the way it does when a reload fails, as the next
+            // poll may well work.
+            let current = match modified_times(&self.posts_directory) {
+                Ok(current) => current,
+                Err(err) => {
+                    eprintln!("error: couldn't look for changes: {}", err);
+                    continue;
+                }
+            };
+            let changed = changed_paths(&snapshot, &current);
+            snapshot = current;
+
+            if changed.is_empty() {
+                continue;
+            }
+            if let Err(err) = self.reload(&changed) {
+                eprintln!("error: {}", err);
+            }
+        }
+    }
+
+    /// Reload the blogs affected by the `changed` files, and render them again
+    /// along with the pages listing every blog. The pages they don't generate
+    /// anymore are removed, like a full build does.
+    fn reload(&mut self, changed: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        for i in self.reload_blogs(changed)? {
+            self.render_blog(&self.blogs[i])?;
+        }
+        self.forget_removed_blogs();
+        self.render_sitemap()?;
+        self.render_combined_feed()?;
+        self.render_opml()?;
+        self.render_not_found()?;
+        self.render_posts_index()?;
+        self.render_search_index()?;
+        self.render_redirects()?;
+        self.signal_live_reload()?;
+        self.remove_stale(false)?;
+        self.cache.save(&self.cache_directory.join(CACHE_FILE))?;
+        Ok(())
+    }
+
+    /// Reload what the `changed` files affect, returning the indices of the
+    /// blogs which were reloaded.
+    fn reload_blogs(&mut self, changed: &[PathBuf]) -> Result<Vec<usize>, Box<dyn Error>> {
+        let mut reloaded = Vec::new();
+        for path in changed {
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            let index = self.blogs.iter().position(|blog| blog.dir() == dir);
+            let file_name = path.file_name().and_then(|n| n.to_str());
+            let is_manifest = file_name == Some(blogs::MANIFEST_FILE);
+            let is_defaults = file_name == Some(blogs::DEFAULTS_FILE);
+
+            match index {
+                // Defaults can affect any blog below them.
+                _ if is_defaults => {
+                    self.blogs = blogs::load(
+                        &self.posts_directory,
+                        &self.exclude,
+                        &self.cache,
+                        &self.config,
+                    )?;
+                    reloaded = (0..self.blogs.len()).collect();
+                    break;
+                }
+                // A manifest change can affect everything in the blog, so load
+                // it from scratch.
+                Some(i) if is_manifest && path.exists() => {
+                    let prefix = self.blogs[i].prefix().to_path_buf();
+                    self.blogs[i] = Blog::load(prefix, dir, &self.cache, &self.config)
+                        .map_err(|e| -> Box<dyn Error> { e })?;
+                    reloaded.push(i);
+                }
+                Some(i) if !is_manifest => {
+                    self.blogs[i]
+                        .reload_post(&Disk, path, &self.cache, &self.config)
+                        .map_err(|e| -> Box<dyn Error> { e })?;
+                    reloaded.push(i);
+                }
+                // Any post of the blog can use an include.
+                None if dir.ends_with(posts::INCLUDES_DIR) => {
+                    let blog_dir = dir.parent().unwrap_or_else(|| Path::new(""));
+                    if let Some(i) = self.blogs.iter().position(|blog| blog.dir() == blog_dir) {
+                        let prefix = self.blogs[i].prefix().to_path_buf();
+                        self.blogs[i] = Blog::load(prefix, blog_dir, &self.cache, &self.config)
+                            .map_err(|e| -> Box<dyn Error> { e })?;
+                        reloaded.push(i);
+                    }
+                }
+                // Blogs were added or removed, so start over.
+                _ if is_manifest => {
+                    self.blogs = blogs::load(
+                        &self.posts_directory,
+                        &self.exclude,
+                        &self.cache,
+                        &self.config,
+                    )?;
+                    reloaded = (0..self.blogs.len()).collect();
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        reloaded.sort_unstable();
+        reloaded.dedup();
+        Ok(reloaded)
+    }
+
+    fn render_template(
+        &self,
+        name: impl AsRef<Path>,
+        template: &str,
+        data: serde_json::Value,
+    ) -> Result<(), Box<dyn Error>> {
+        let rendered = self.handlebars.render(template, &data)?;
+        self.write_file(name, rendered)
+    }
+
+    fn write_file(
+        &self,
+        name: impl AsRef<Path>,
+        contents: impl AsRef<[u8]>,
+    ) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        let hash = format!("{:016x}", assets::fnv1a(contents.as_ref()));
+        let path = self.out_directory.join(name);
+        // Files the previous run already generated as they are now are left
+        // alone, so tools syncing the site only see the ones which changed.
+        if self.previous_hashes.get(name) != Some(&hash) || !path.is_file() {
+            fs::write(path, contents)?;
+        }
+        self.hashes.borrow_mut().insert(name.to_path_buf(), hash);
+        self.written.borrow_mut().insert(name.to_path_buf());
+        Ok(())
+    }
+}
+
+/// The file of the page of a post, relative to the output directory.
+fn post_file(blog: &Blog, post: &Post) -> PathBuf {
+    if post.url.ends_with('/') {
+        blog.prefix().join(&post.url).join("index.html")
+    } else {
+        blog.prefix().join(&post.url)
+    }
+}
+
+/// The directory of the cache in which what is generated in `out_directory` is
+/// recorded for the next run, named after a hash of its absolute path. It's
+/// kept out of the output directory, so it isn't published with the site.
+fn output_records(cache_directory: &Path, out_directory: &Path) -> Result<PathBuf, io::Error> {
+    let out_directory = std::path::absolute(out_directory)?;
+    let hash = assets::fnv1a(out_directory.to_string_lossy().as_bytes());
+    Ok(cache_directory
+        .join(OUTPUTS_DIRECTORY)
+        .join(format!("{:016x}", hash)))
+}
+
+/// The hashes `save_generated_list` recorded in `output_records` in the
+/// previous run, which are none for the first one.
+fn load_output_hashes(output_records: &Path) -> Result<HashMap<PathBuf, String>, Box<dyn Error>> {
+    let list = match fs::read_to_string(output_records.join(OUTPUT_HASHES_FILE)) {
+        Ok(list) => list,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(list
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, file)| (PathBuf::from(file), hash.to_string()))
+        .collect())
+}
+
+/// The most recent posts of a blog to put in a feed. The links in their
+/// contents are made absolute, as not all feed readers support `xml:base`.
+fn feed_posts<'a>(
+    blog: &Blog,
+    posts: impl IntoIterator<Item = &'a Post>,
+) -> Result<Vec<serde_json::Value>, Box<dyn Error>> {
+    let mut feed_posts = Vec::new();
+    for post in posts.into_iter().take(blog.feed_length()) {
+        let source = format!("{}{}", blog.url_prefix(), post.url);
+        let mut value = serde_json::to_value(post)?;
+        value["contents"] = links::make_absolute(&post.contents, blog.base_url(), &source).into();
+        feed_posts.push(value);
+    }
+    Ok(feed_posts)
+}
+
+/// The `limit` latest posts of all of `blogs`, newest first, for a feed. Each
+/// knows which blog it's from.
+fn combined_feed_posts<'a>(
+    blogs: impl Iterator<Item = &'a Blog>,
+    limit: usize,
+) -> Result<Vec<serde_json::Value>, Box<dyn Error>> {
+    let mut posts: Vec<(&Blog, &Post)> = blogs
+        .flat_map(|blog| blog.posts().iter().map(move |post| (blog, post)))
+        .collect();
+    // with a stable order between posts of the same day
+    posts.sort_by(|(a_blog, a), (b_blog, b)| {
+        (b.year, b.month, b.day)
+            .cmp(&(a.year, a.month, a.day))
+            .then_with(|| a_blog.url_prefix().cmp(&b_blog.url_prefix()))
+            .then_with(|| a.url.cmp(&b.url))
+    });
+
+    let mut feed_posts = Vec::new();
+    for (blog, post) in posts.into_iter().take(limit) {
+        let source = format!("{}{}", blog.url_prefix(), post.url);
+        let mut value = serde_json::to_value(post)?;
+        value["contents"] = links::make_absolute(&post.contents, blog.base_url(), &source).into();
+        value["url"] = blog.absolute_url(&post.url).into();
+        value["blog"] = json!({
+            "title": blog.title(),
+            "url": blog.absolute_url(""),
+            "feed_url": blog.absolute_url(FEED_PATHS[0]),
+            "feed_full_content": blog.feed_full_content(),
+        });
+        feed_posts.push(value);
+    }
+    Ok(feed_posts)
+}
+
+/// When a feed of the posts was last updated, i.e. the latest time one of its
+/// posts was. Readers would think the feed changed with every build if it was
+/// the time of the build, which is only used for feeds without posts.
+fn feed_updated<'a>(blog: &Blog, posts: impl IntoIterator<Item = &'a Post>) -> String {
+    posts
+        .into_iter()
+        .take(blog.feed_length())
+        .map(|post| post.updated.clone())
+        .max()
+        .unwrap_or_else(build_time)
+}
+
+/// The time of the build, for what doesn't have a date of its own, like feeds
+/// without posts. It's taken from the `SOURCE_DATE_EPOCH` environment variable
+/// when set to a number of seconds, so builds can be reproduced exactly.
+fn build_time() -> String {
+    std::env::var(SOURCE_DATE_EPOCH_VAR)
+        .ok()
+        .and_then(|seconds| seconds.trim().parse().ok())
+        .and_then(|seconds| chrono::Utc.timestamp_opt(seconds, 0).single())
+        .unwrap_or_else(|| chrono::Utc::now().with_nanosecond(0).unwrap())
+        .to_rfc3339()
+}
+
+/// The alternate links connecting a post to its translations, including the
+/// post itself and an `x-default` one for the version in the default language.
+/// Posts without translations don't get any.
+fn hreflang_links(
+    blog: &Blog,
+    post: &Post,
+    translations: &BTreeMap<&str, &str>,
+) -> Vec<serde_json::Value> {
+    if translations.is_empty() {
+        return Vec::new();
+    }
+    let href = |url: &str| blog.absolute_url(url);
+    let mut versions = translations.clone();
+    versions.insert(&post.lang, &post.url);
+
+    let default = versions
+        .get(posts::DEFAULT_LANG)
+        .copied()
+        .unwrap_or(&post.url);
+    versions
+        .iter()
+        .map(|(lang, url)| json!({ "lang": lang, "href": href(url) }))
+        .chain(std::iter::once(
+            json!({ "lang": "x-default", "href": href(default) }),
+        ))
+        .collect()
+}
+
+/// schema.org `Article` structured data describing a post, ready to be
+/// embedded in a `<script>` element.
+fn article_json_ld(blog: &Blog, post: &Post) -> String {
+    let authors: Vec<_> = post
+        .authors
+        .iter()
+        .map(|name| json!({ "@type": "Person", "name": name }))
+        .collect();
+    // a single author isn't wrapped in an array
+    let author = if authors.len() == 1 {
+        authors.into_iter().next().unwrap()
+    } else {
+        serde_json::Value::Array(authors)
+    };
+    let mut data = json!({
+        "@context": "https://schema.org",
+        "@type": "Article",
+        "headline": post.title,
+        "inLanguage": post.lang,
+        "description": post.description,
+        "url": blog.absolute_url(&post.url),
+        "image": post.preview_image,
+        "datePublished": post.published,
+        "dateModified": post.updated,
+        "author": author,
+    });
+    if post.preview_image.is_none() {
+        data.as_object_mut().unwrap().remove("image");
+    }
+    // `</script>` in a title must not end the element early
+    data.to_string().replace("</", "<\\/")
+}
+
+/// The files which were added, modified or removed between two snapshots of
+/// `modified_times`, sorted.
+fn changed_paths(
+    snapshot: &HashMap<PathBuf, SystemTime>,
+    current: &HashMap<PathBuf, SystemTime>,
+) -> Vec<PathBuf> {
+    let mut changed: Vec<_> = current
+        .iter()
+        .filter(|(path, modified)| snapshot.get(*path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .chain(
+            snapshot
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .cloned(),
+        )
+        .collect();
+    changed.sort();
+    changed
+}
+
+/// The last modification time of every file below `dir`. Files and
+/// directories removed while they are listed, like the temporary files of
+/// editors, are left out.
+fn modified_times(dir: &Path) -> Result<HashMap<PathBuf, SystemTime>, io::Error> {
+    fn collect(dir: &Path, times: &mut HashMap<PathBuf, SystemTime>) -> Result<(), io::Error> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = match entry.metadata() {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                metadata => metadata?,
+            };
+            if metadata.is_dir() {
+                match collect(&entry.path(), times) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    result => result?,
+                }
+            } else {
+                times.insert(entry.path(), metadata.modified()?);
+            }
+        }
+        Ok(())
+    }
+    let mut times = HashMap::new();
+    collect(dir, &mut times)?;
+    Ok(times)
+}
+
+/// Copy the directory `source` into `dest`, returning the files copied.
+fn copy_dir(source: impl AsRef<Path>, dest: impl AsRef<Path>) -> Result<Vec<PathBuf>, io::Error> {
+    let source = source.as_ref();
+    let dest = dest.as_ref().join(source.file_name().unwrap());
+    assert!(source.is_dir());
+    fn copy_inner(source: &Path, dest: &Path, copied: &mut Vec<PathBuf>) -> Result<(), io::Error> {
+        fs::create_dir_all(dest)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            let new_dest = dest.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                copy_inner(&entry.path(), &new_dest, copied)?;
+            } else {
+                fs::copy(entry.path(), &new_dest)?;
+                copied.push(new_dest);
+            }
+        }
+        Ok(())
+    }
+    let mut copied = Vec::new();
+    copy_inner(source, &dest, &mut copied)?;
+    Ok(copied)
+}
+
+static USAGE: &str = "usage: blog [--output-dir <dir>] [--dry-run] [--deny-warnings] \
+                     [--deny-future-posts] [--changed] [--check | --watch]";
+
+/// The options given on the command line.
+#[derive(Debug)]
+struct Args {
+    /// `--output-dir <dir>`, `site` by default.
+    out_directory: PathBuf,
+    /// `--dry-run`: list the stale files of previous runs instead of removing
+    /// them.
+    dry_run: bool,
+    /// `--deny-warnings`: fail once the site is generated if anything was
+    /// reported, even only as a warning.
+    deny_warnings: bool,
+    /// `--changed`: list the urls of the posts whose page changed since the
+    /// previous run.
+    changed: bool,
+    /// `--deny-future-posts`, or the `DENY_FUTURE_POSTS` environment
+    /// variable: fail if a post is dated in the future.
+    deny_future_posts: bool,
+    /// The `DIAGNOSTICS_FILE` environment variable: where the problems found
+    /// are written as JSON.
+    diagnostics_file: Option<PathBuf>,
+}
+
+impl Args {
+    /// Parse the arguments following the name of the program, printing the
+    /// usage if one of them isn't known.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = Args {
+            out_directory: PathBuf::from("site"),
+            dry_run: false,
+            deny_warnings: false,
+            changed: false,
+            deny_future_posts: std::env::var_os(DENY_FUTURE_POSTS_VAR).is_some(),
+            diagnostics_file: std::env::var_os(DIAGNOSTICS_FILE_VAR).map(PathBuf::from),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output-dir" => {
+                    parsed.out_directory =
+                        args.next().ok_or("--output-dir needs a directory")?.into();
+                }
+                "--dry-run" => parsed.dry_run = true,
+                "--deny-warnings" => parsed.deny_warnings = true,
+                "--changed" => parsed.changed = true,
+                "--deny-future-posts" => parsed.deny_future_posts = true,
+                // handled by the `blog` binary
+                "--check" | "--watch" => {}
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return Err(format!("unknown argument `{}`", arg).into());
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Generate the blog, returning the directory it was generated in.
+pub fn main() -> Result<PathBuf, Box<dyn Error>> {
+    let args = Args::parse(std::env::args().skip(1))?;
+    let mut blog = Generator::new(
+        Config::from_env(),
+        &args.out_directory,
+        "posts",
+        CACHE_DIRECTORY,
+    )?;
+    blog.deny_future_posts = args.deny_future_posts;
+    blog.diagnostics_file = args.diagnostics_file.clone();
+
+    blog.render()?;
+    blog.remove_stale(args.dry_run)?;
+    if args.changed {
+        for url in blog.changed_posts() {
+            println!("changed post {}", url);
+        }
+    }
+
+    if args.deny_warnings {
+        blog.deny_warnings()?;
+    }
+    Ok(args.out_directory)
+}
+
+/// Check the blog like generating it would, without writing anything.
+pub fn check() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(std::env::args().skip(1))?;
+    let mut blog = Generator::new(
+        Config::from_env(),
+        &args.out_directory,
+        "posts",
+        CACHE_DIRECTORY,
+    )?;
+    blog.deny_future_posts = args.deny_future_posts;
+    blog.diagnostics_file = args.diagnostics_file.clone();
+
+    blog.check()?;
+
+    if args.deny_warnings {
+        blog.deny_warnings()?;
+    }
+    Ok(())
+}
+
+/// Generate the blog, then keep regenerating it as its sources change. Open
+/// pages reload themselves when served over HTTP. `on_ready` is called with
+/// the output directory once the blog was first generated. `args` are the
+/// command line arguments of the blog, without the name of the program.
+pub fn watch(
+    args: impl IntoIterator<Item = String>,
+    on_ready: impl FnOnce(&Path),
+) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(args)?;
+    let mut blog = Generator::new(
+        Config::from_env(),
+        &args.out_directory,
+        "posts",
+        CACHE_DIRECTORY,
+    )?;
+    blog.deny_future_posts = args.deny_future_posts;
+    blog.diagnostics_file = args.diagnostics_file.clone();
+    blog.enable_live_reload()?;
+
+    blog.render()?;
+    blog.remove_stale(args.dry_run)?;
+    on_ready(&args.out_directory);
+    println!("watching `posts` for changes...");
+    blog.watch()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{self, post, TempDir};
+    use super::*;
+
+    /// The sources of a site with a single blog, and where builds of it go.
+    struct Site {
+        dir: TempDir,
+    }
+
+    impl Site {
+        fn new(files: &[(&str, &str)]) -> Self {
+            let dir = TempDir::new();
+            dir.write("posts/blog.yml", testing::MANIFEST);
+            for (path, contents) in files {
+                dir.write(&format!("posts/{}", path), contents);
+            }
+            Site { dir }
+        }
+
+        fn generator(&self) -> Generator<'static> {
+            self.generator_in("site")
+        }
+
+        /// A generator writing to the `out` directory of the site.
+        fn generator_in(&self, out: &str) -> Generator<'static> {
+            let path = self.dir.path();
+            Generator::new(
+                Config::default(),
+                path.join(out),
+                path.join("posts"),
+                path.join("cache"),
+            )
+            .unwrap()
+        }
+
+        /// Generate the pages of the blogs, like a run does before the rest of
+        /// the site.
+        fn build(&self) -> Generator<'static> {
+            let generator = self.generator();
+            for blog in &generator.blogs {
+                generator.render_blog(blog).unwrap();
+            }
+            generator
+        }
+    }
+
+    /// The contents of the `name` elements of some XML, which are enough to
+    /// tell what a feed has.
+    fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+        let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+        xml.match_indices(&open)
+            .map(|(i, _)| {
+                let start = i + open.len();
+                &xml[start..start + xml[start..].find(&close).unwrap()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stale_files_are_removed() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            ("2020-02-01-second.md", &post("Second", "", "")),
+        ]);
+        site.build().remove_stale(false).unwrap();
+        assert!(site.dir.exists("site/2020/01/01/first.html"));
+
+        std::fs::remove_file(site.dir.path().join("posts/2020-01-01-first.md")).unwrap();
+        site.build().remove_stale(false).unwrap();
+        assert!(!site.dir.exists("site/2020/01/01"));
+        assert!(site.dir.exists("site/2020/02/01/second.html"));
+
+        // the records of what was generated aren't published with the site
+        let records = output_records(
+            &site.dir.path().join("cache"),
+            &site.dir.path().join("site"),
+        )
+        .unwrap();
+        assert!(records.join(GENERATED_LIST_FILE).is_file());
+        assert!(records.join(OUTPUT_HASHES_FILE).is_file());
+        for record in [
+            GENERATED_LIST_FILE,
+            OUTPUT_HASHES_FILE,
+            ".generated",
+            ".hashes",
+        ] {
+            assert!(!site.dir.exists(&format!("site/{}", record)));
+        }
+    }
+
+    #[test]
+    fn dry_runs_keep_stale_files() {
+        let site = Site::new(&[("2020-01-01-first.md", &post("First", "", ""))]);
+        site.build().remove_stale(false).unwrap();
+        site.dir.write(
+            "posts/2020-01-01-first.md",
+            post("First", "slug: renamed\n", ""),
+        );
+
+        site.build().remove_stale(true).unwrap();
+        assert!(site.dir.exists("site/2020/01/01/first.html"));
+        // they are still stale for the next run
+        site.build().remove_stale(false).unwrap();
+        assert!(!site.dir.exists("site/2020/01/01/first.html"));
+        assert!(site.dir.exists("site/2020/01/01/renamed.html"));
+    }
+
+    #[test]
+    fn changed_posts_are_the_ones_whose_page_changed() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "Hello")),
+            ("2020-02-01-second.md", &post("Second", "", "Hello")),
+        ]);
+        site.build().remove_stale(false).unwrap();
+
+        site.dir
+            .write("posts/2020-01-01-first.md", post("First", "", "Hi"));
+        let generator = site.build();
+        assert_eq!(
+            generator.changed_posts(),
+            ["https://blog.rust-lang.org/2020/01/01/first.html"]
+        );
+        generator.remove_stale(false).unwrap();
+
+        assert_eq!(site.build().changed_posts(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn tag_feeds_have_the_tagged_posts_only() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post("First", "tags: [Compiler]\n", ""),
+            ),
+            (
+                "2020-02-01-second.md",
+                &post("Second", "tags: [Cargo]\n", ""),
+            ),
+            (
+                "2020-03-01-third.md",
+                &post("Third", "tags: [Compiler, Cargo]\n", ""),
+            ),
+        ]);
+        site.build();
+        let feed = site.dir.read("site/tags/compiler/feed.xml");
+        assert_eq!(
+            elements(&feed, "title"),
+            ["Test Blog – Compiler", "Third", "First"]
+        );
+        assert!(feed.contains(
+            r#"<link href="https://blog.rust-lang.org/tags/compiler/feed.xml" rel="self" type="application/atom+xml" />"#
+        ));
+        assert!(feed.contains(
+            r#"<link href="https://blog.rust-lang.org/tags/compiler/" rel="alternate" type="text/html" />"#
+        ));
+        assert!(feed.contains("<id>https://blog.rust-lang.org/tags/compiler/</id>"));
+
+        // the feed of the blog keeps its own
+        let feed = site.dir.read("site/feed.xml");
+        assert!(feed.contains("<id>https://blog.rust-lang.org/</id>"));
+        assert!(feed.contains("<title>Test Blog</title>"));
+    }
+
+    #[test]
+    fn unknown_arguments_are_rejected() {
+        let args = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+
+        let parsed = args(&["--output-dir", "out", "--deny-warnings", "--check"]).unwrap();
+        assert_eq!(parsed.out_directory, Path::new("out"));
+        assert!(parsed.deny_warnings);
+        assert!(!parsed.dry_run);
+
+        let err = args(&["--dry-run", "--deny-warning"]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown argument `--deny-warning`");
+        let err = args(&["--output-dir"]).unwrap_err();
+        assert_eq!(err.to_string(), "--output-dir needs a directory");
+    }
+
+    #[test]
+    fn warnings_can_be_denied() {
+        let site = Site::new(&[(
+            "2020-01-01-first.md",
+            &post("First", "", "[next](/2020/02/01/missing.html)"),
+        )]);
+        let generator = site.generator();
+        generator.check().unwrap();
+        assert_eq!(generator.diagnostics.count(Severity::Warning), 1);
+        let err = generator.deny_warnings().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "found 1 warning(s), denied by --deny-warnings"
+        );
+
+        site.dir
+            .write("posts/2020-01-01-first.md", post("First", "", "Hello"));
+        let generator = site.generator();
+        generator.check().unwrap();
+        generator.deny_warnings().unwrap();
+    }
+
+    #[test]
+    fn checks_links_to_posts_with_their_permalinks() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            (
+                "2020-02-01-second.md",
+                &post(
+                    "Second",
+                    "",
+                    "[first](/2020/first/) [missing](/2020/missing/) [feed](/feed.xml)",
+                ),
+            ),
+        ]);
+        site.dir.write(
+            "posts/blog.yml",
+            testing::manifest("permalink: \":year/:slug/\"\n"),
+        );
+        let generator = site.generator();
+        generator.check().unwrap();
+        assert_eq!(generator.diagnostics.count(Severity::Warning), 1);
+        assert!(!site.dir.exists("site"));
+    }
+
+    #[test]
+    fn checks_report_bad_posts_without_writing_anything() {
+        let site = Site::new(&[(
+            "2020-01-01-first.md",
+            &post("First", "layout: missing\n", ""),
+        )]);
+        let err = site.generator().check().unwrap_err();
+        let post = site.dir.path().join("posts/2020-01-01-first.md");
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "blog post at path `{}` has an unknown layout `missing`",
+                post.display()
+            )
+        );
+        assert!(!site.dir.exists("site"));
+    }
+
+    #[test]
+    fn future_posts_are_only_denied_on_demand() {
+        let site = Site::new(&[
+            ("2020-01-01-past.md", &post("Past", "", "")),
+            ("2999-01-01-future.md", &post("Future", "", "")),
+        ]);
+        let mut generator = site.generator();
+        generator.check_dates().unwrap();
+        assert_eq!(generator.diagnostics.count(Severity::Error), 0);
+
+        generator.deny_future_posts = true;
+        let err = generator.check_dates().unwrap_err();
+        assert_eq!(err.to_string(), "found 1 post(s) dated in the future");
+        assert_eq!(generator.diagnostics.count(Severity::Error), 1);
+    }
+
+    #[test]
+    fn changed_paths_are_the_added_modified_and_removed_files() {
+        let time = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let snapshot: HashMap<_, _> = [
+            (PathBuf::from("posts/kept.md"), time(1)),
+            (PathBuf::from("posts/modified.md"), time(1)),
+            (PathBuf::from("posts/removed.md"), time(1)),
+        ]
+        .into_iter()
+        .collect();
+        let current: HashMap<_, _> = [
+            (PathBuf::from("posts/kept.md"), time(1)),
+            (PathBuf::from("posts/modified.md"), time(2)),
+            (PathBuf::from("posts/added.md"), time(2)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            changed_paths(&snapshot, &current),
+            [
+                Path::new("posts/added.md"),
+                Path::new("posts/modified.md"),
+                Path::new("posts/removed.md"),
+            ]
+        );
+        assert_eq!(changed_paths(&current, &current), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn only_the_blog_of_a_changed_post_is_reloaded() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            ("inside-rust/blog.yml", testing::MANIFEST),
+            ("inside-rust/2020-01-01-inside.md", &post("Inside", "", "")),
+        ]);
+        let mut generator = site.generator();
+        let posts = site.dir.path().join("posts");
+        let inside = generator
+            .blogs
+            .iter()
+            .position(|blog| blog.prefix() == Path::new("inside-rust"))
+            .unwrap();
+        let snapshot = modified_times(&posts).unwrap();
+
+        // simulate saving the post a second later
+        let path = posts.join("inside-rust/2020-01-01-inside.md");
+        site.dir.write(
+            "posts/inside-rust/2020-01-01-inside.md",
+            post("Renamed", "", ""),
+        );
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(snapshot[&path] + Duration::from_secs(1))
+            .unwrap();
+        let changed = changed_paths(&snapshot, &modified_times(&posts).unwrap());
+        assert_eq!(changed, [path]);
+
+        assert_eq!(generator.reload_blogs(&changed).unwrap(), [inside]);
+        assert_eq!(generator.blogs[inside].posts()[0].title, "Renamed");
+    }
+
+    #[test]
+    fn the_same_posts_generate_the_same_bytes() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post(
+                    "First",
+                    "authors: [Ferris, Bors]\ntags: [Compiler, Cargo]\n",
+                    "# One\n\nHello",
+                ),
+            ),
+            (
+                "2020-02-01-second.md",
+                &post(
+                    "Second",
+                    "authors: [Bors, Ferris]\ntags: [Cargo, Docs]\nlang: fr\n",
+                    "Bonjour",
+                ),
+            ),
+            (
+                "2020-02-01-third.md",
+                &post("Third", "tags: [Docs, Compiler]\n", "Hi"),
+            ),
+            ("inside-rust/blog.yml", testing::MANIFEST),
+            (
+                "inside-rust/2020-03-01-inside.md",
+                &post("Inside", "tags: [Compiler]\n", "Hi"),
+            ),
+        ]);
+        let build = |out: &str| {
+            site.generator_in(out).render_pages().unwrap();
+            let mut files = BTreeMap::new();
+            let out = site.dir.path().join(out);
+            for path in modified_times(&out).unwrap().into_keys() {
+                let contents = fs::read(&path).unwrap();
+                files.insert(path.strip_prefix(&out).unwrap().to_path_buf(), contents);
+            }
+            files
+        };
+
+        let first = build("first");
+        assert!(first.contains_key(Path::new("tags/docs/feed.xml")));
+        assert!(first.contains_key(Path::new("inside-rust/index.html")));
+        let second = build("second");
+        assert_eq!(
+            first.keys().collect::<Vec<_>>(),
+            second.keys().collect::<Vec<_>>()
+        );
+        for (path, contents) in &first {
+            assert!(contents == &second[path], "`{}` differs", path.display());
+        }
+    }
+
+    #[test]
+    fn feeds_are_also_published_as_atom_xml() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            (
+                "2020-02-01-second.md",
+                &post("Second", "updated: 2020-03-01\n", ""),
+            ),
+        ]);
+        site.build();
+        let feed = site.dir.read("site/atom.xml");
+        assert_eq!(feed.matches("<entry>").count(), 2);
+        assert_eq!(
+            elements(&feed, "id"),
+            [
+                "https://blog.rust-lang.org/",
+                "https://blog.rust-lang.org/2020/02/01/second.html",
+                "https://blog.rust-lang.org/2020/01/01/first.html",
+            ]
+        );
+        assert_eq!(
+            elements(&feed, "updated"),
+            [
+                "2020-03-01T00:00:00+00:00",
+                "2020-03-01T00:00:00+00:00",
+                "2020-01-01T00:00:00+00:00",
+            ]
+        );
+        // it's the same feed as the historical one, apart from its own link
+        let historical = site.dir.read("site/feed.xml");
+        assert_eq!(feed.replace("atom.xml", "feed.xml"), historical);
+    }
+
+    #[test]
+    fn the_sitemap_has_every_blog_and_post() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            (
+                "2020-02-01-second.md",
+                &post("Second", "updated: 2020-03-01\n", ""),
+            ),
+            ("inside-rust/blog.yml", testing::MANIFEST),
+            ("inside-rust/2020-04-01-inside.md", &post("Inside", "", "")),
+        ]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        site.generator().render_sitemap().unwrap();
+        let sitemap = site.dir.read("site/sitemap.xml");
+        assert_eq!(
+            elements(&sitemap, "loc"),
+            [
+                "https://blog.rust-lang.org/",
+                "https://blog.rust-lang.org/2020/02/01/second.html",
+                "https://blog.rust-lang.org/2020/01/01/first.html",
+                "https://blog.rust-lang.org/inside-rust/",
+                "https://blog.rust-lang.org/inside-rust/2020/04/01/inside.html",
+            ]
+        );
+        assert_eq!(
+            elements(&sitemap, "lastmod"),
+            [
+                "2020-03-01",
+                "2020-03-01",
+                "2020-01-01",
+                "2020-04-01",
+                "2020-04-01"
+            ]
+        );
+    }
+
+    #[test]
+    fn posts_describe_themselves_to_social_media() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post(
+                    "First",
+                    "description: All about it\nimage: /images/first.png\n",
+                    "",
+                ),
+            ),
+            (
+                "2020-02-01-second.md",
+                &post("Second", "", "The first paragraph.\n\nThe second one."),
+            ),
+        ]);
+        site.build();
+        let page = site.dir.read("site/2020/01/01/first.html");
+        for tag in [
+            r#"<meta property="og:title" content="First" />"#,
+            r#"<meta property="og:description" content="All about it">"#,
+            r#"<meta property="og:url" content="https://blog.rust-lang.org/2020/01/01/first.html" />"#,
+            r#"<meta property="og:image" content="https://blog.rust-lang.org/images/first.png" />"#,
+            r#"<meta property="og:type" content="article" />"#,
+            r#"<meta name="twitter:card" content="summary_large_image">"#,
+            r#"<meta name="twitter:description" content="All about it">"#,
+        ] {
+            assert!(page.contains(tag), "{} isn't in\n{}", tag, page);
+        }
+
+        // without a description, the first paragraph is one
+        let page = site.dir.read("site/2020/02/01/second.html");
+        for tag in [
+            r#"<meta property="og:description" content="The first paragraph.">"#,
+            r#"<meta name="twitter:card" content="summary">"#,
+        ] {
+            assert!(page.contains(tag), "{} isn't in\n{}", tag, page);
+        }
+    }
+
+    #[test]
+    fn the_posts_index_lists_every_post() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post(
+                    "First",
+                    "authors: [Ferris, Bors]\ntags: [Cargo]\ndescription: About it\n",
+                    "# Hello\n\nSome words",
+                ),
+            ),
+            ("2020-01-01-second.md", &post("Second", "", "")),
+            ("inside-rust/blog.yml", testing::MANIFEST),
+            ("inside-rust/2020-02-01-inside.md", &post("Inside", "", "")),
+        ]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        site.generator().render_posts_index().unwrap();
+        let index: serde_json::Value =
+            serde_json::from_str(&site.dir.read("site/posts.json")).unwrap();
+        let urls: Vec<_> = index["posts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|post| post["url"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "inside-rust/2020/02/01/inside.html",
+                "2020/01/01/first.html",
+                "2020/01/01/second.html",
+            ]
+        );
+        let first = &index["posts"][1];
+        assert_eq!(first["title"], "First");
+        assert_eq!(first["date"], "2020-01-01");
+        assert_eq!(first["authors"], json!(["Ferris", "Bors"]));
+        assert_eq!(first["tags"], json!(["Cargo"]));
+        assert_eq!(first["lang"], "en");
+        assert_eq!(first["excerpt"], "About it");
+        assert_eq!(first["word_count"], 4);
+        assert_eq!(first["heading_count"], 1);
+    }
+
+    #[test]
+    fn pages_advertise_the_feeds_of_their_blog() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            ("inside-rust/blog.yml", testing::MANIFEST),
+            ("inside-rust/2020-01-01-inside.md", &post("Inside", "", "")),
+        ]);
+        site.build();
+        for (page, prefix) in [
+            ("site/index.html", ""),
+            ("site/2020/01/01/first.html", ""),
+            ("site/inside-rust/index.html", "inside-rust/"),
+            ("site/inside-rust/2020/01/01/inside.html", "inside-rust/"),
+        ] {
+            let page = site.dir.read(page);
+            for (kind, feed) in [
+                ("application/atom+xml", "feed.xml"),
+                ("application/feed+json", "feed.json"),
+            ] {
+                let link = format!(
+                    r#"<link type="{}" rel="alternate" href="https://blog.rust-lang.org/{}{}" title="Test Blog" />"#,
+                    kind, prefix, feed
+                );
+                assert!(page.contains(&link), "{} isn't in\n{}", link, page);
+            }
+        }
+    }
+
+    /// The JSON-LD data embedded in a page.
+    fn json_ld(page: &str) -> serde_json::Value {
+        let open = r#"<script type="application/ld+json">"#;
+        let start = page.find(open).unwrap() + open.len();
+        let end = start + page[start..].find("</script>").unwrap();
+        serde_json::from_str(&page[start..end]).unwrap()
+    }
+
+    #[test]
+    fn posts_have_json_ld_article_data() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post(
+                    "First </script>",
+                    "authors: Ferris\nupdated: 2020-01-05\n",
+                    "",
+                ),
+            ),
+            (
+                "2020-02-01-second.md",
+                &post("Second", "authors: [Ferris, Bors]\n", ""),
+            ),
+        ]);
+        site.build();
+
+        let data = json_ld(&site.dir.read("site/2020/01/01/first.html"));
+        assert_eq!(data["@context"], "https://schema.org");
+        assert_eq!(data["@type"], "Article");
+        assert_eq!(data["headline"], "First </script>");
+        assert_eq!(
+            data["url"],
+            "https://blog.rust-lang.org/2020/01/01/first.html"
+        );
+        for date in ["datePublished", "dateModified"] {
+            let date = data[date].as_str().unwrap();
+            assert!(
+                chrono::DateTime::parse_from_rfc3339(date).is_ok(),
+                "{}",
+                date
+            );
+        }
+        assert!(data["datePublished"]
+            .as_str()
+            .unwrap()
+            .starts_with("2020-01-01"));
+        assert!(data["dateModified"]
+            .as_str()
+            .unwrap()
+            .starts_with("2020-01-05"));
+        assert_eq!(
+            data["author"],
+            json!({ "@type": "Person", "name": "Ferris" })
+        );
+
+        let data = json_ld(&site.dir.read("site/2020/02/01/second.html"));
+        assert_eq!(
+            data["author"],
+            json!([
+                { "@type": "Person", "name": "Ferris" },
+                { "@type": "Person", "name": "Bors" },
+            ])
+        );
+    }
+
+    #[test]
+    fn the_search_index_has_a_document_per_published_post() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post("First", "tags: [Cargo]\n", "# Hello\n\nSome *words*"),
+            ),
+            ("2020-02-01-second.md", &post("Second", "", "")),
+            (
+                "2020-03-01-draft.md",
+                &post("Draft", "published: false\n", ""),
+            ),
+            ("inside-rust/blog.yml", testing::MANIFEST),
+            ("inside-rust/2020-02-01-inside.md", &post("Inside", "", "")),
+        ]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        site.generator().render_search_index().unwrap();
+        let index = site.dir.read("site/search-index.json");
+        let documents: serde_json::Value = serde_json::from_str(&index).unwrap();
+        let ids: Vec<_> = documents
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|document| document["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                "2020/01/01/first.html",
+                "2020/02/01/second.html",
+                "inside-rust/2020/02/01/inside.html",
+            ]
+        );
+        assert_eq!(
+            documents[0],
+            json!({
+                "id": "2020/01/01/first.html",
+                "title": "First",
+                "url": "2020/01/01/first.html",
+                "tags": ["Cargo"],
+                "body": "Hello Some words",
+            })
+        );
+
+        // the same bytes on the next build
+        site.generator().render_search_index().unwrap();
+        assert_eq!(site.dir.read("site/search-index.json"), index);
+    }
+
+    /// The `hreflang` alternate links of a page.
+    fn hreflang_links(page: &str) -> Vec<&str> {
+        page.lines()
+            .filter(|line| line.starts_with("<link") && line.contains("hreflang="))
+            .collect()
+    }
+
+    #[test]
+    fn translated_posts_link_to_each_other_with_hreflang() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-hello.md",
+                &post("Hello", "translations: {es: 2020/01/01/hola.html}\n", ""),
+            ),
+            (
+                "2020-01-01-hola.md",
+                &post(
+                    "Hola",
+                    "lang: es\ntranslations: {fr: 2020/01/01/salut.html}\n",
+                    "",
+                ),
+            ),
+            ("2020-01-01-salut.md", &post("Salut", "lang: fr\n", "")),
+            ("2020-02-01-other.md", &post("Other", "", "")),
+        ]);
+        site.build();
+        let expected = [
+            r#"<link rel="alternate" hreflang="en" href="https://blog.rust-lang.org/2020/01/01/hello.html" />"#,
+            r#"<link rel="alternate" hreflang="es" href="https://blog.rust-lang.org/2020/01/01/hola.html" />"#,
+            r#"<link rel="alternate" hreflang="fr" href="https://blog.rust-lang.org/2020/01/01/salut.html" />"#,
+            r#"<link rel="alternate" hreflang="x-default" href="https://blog.rust-lang.org/2020/01/01/hello.html" />"#,
+        ];
+        for page in ["hello", "hola", "salut"] {
+            let page = site.dir.read(&format!("site/2020/01/01/{}.html", page));
+            assert_eq!(hreflang_links(&page), expected);
+        }
+        let page = site.dir.read("site/2020/02/01/other.html");
+        assert_eq!(hreflang_links(&page), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn feeds_have_the_newest_posts_only() {
+        let site = Site::new(&[
+            ("blog.yml", &testing::manifest("feed-length: 2\n")),
+            ("2020-01-01-first.md", &post("First", "", "")),
+            ("2020-03-01-third.md", &post("Third", "", "")),
+            ("2020-02-01-second.md", &post("Second", "", "")),
+            ("2020-04-01-fourth.md", &post("Fourth", "", "")),
+        ]);
+        site.build();
+        let feed = site.dir.read("site/feed.xml");
+        assert_eq!(elements(&feed, "title"), ["Test Blog", "Fourth", "Third"]);
+        let feed: serde_json::Value =
+            serde_json::from_str(&site.dir.read("site/feed.json")).unwrap();
+        let titles: Vec<_> = feed["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, ["Fourth", "Third"]);
+
+        // the index still lists every post
+        let index = site.dir.read("site/index.html");
+        for title in ["First", "Second", "Third", "Fourth"] {
+            assert!(index.contains(&format!(">{}</a>", title)), "{}", title);
+        }
+
+        // blogs without a length keep the 10 newest posts
+        assert_eq!(testing::parse_manifest(testing::MANIFEST).feed_length, 10);
+    }
+
+    #[test]
+    fn feeds_carry_the_full_content_unless_told_otherwise() {
+        let body = "See [the next one](../../02/01/second.html) and ![a crab](/images/crab.png).";
+        let site = Site::new(&[(
+            "2020-01-01-first.md",
+            &post("First", "description: About it\n", body),
+        )]);
+        site.build();
+        let feed = site.dir.read("site/feed.xml");
+        assert_eq!(elements(&feed, "summary"), Vec::<&str>::new());
+        let content = feed.split("<content ").nth(1).unwrap();
+        assert!(content.contains(
+            "&lt;a href&#x3D;&quot;https://blog.rust-lang.org/2020/02/01/second.html&quot;&gt;the next one&lt;/a&gt;"
+        ));
+        assert!(content
+            .contains("&lt;img src&#x3D;&quot;https://blog.rust-lang.org/images/crab.png&quot;"));
+        let feed: serde_json::Value =
+            serde_json::from_str(&site.dir.read("site/feed.json")).unwrap();
+        let item = &feed["items"][0];
+        assert!(item["content_html"]
+            .as_str()
+            .unwrap()
+            .contains(r#"<a href="https://blog.rust-lang.org/2020/02/01/second.html">"#));
+        assert_eq!(item["content_text"], "See the next one and a crab.");
+
+        site.dir.write(
+            "posts/blog.yml",
+            testing::manifest("feed-full-content: false\n"),
+        );
+        site.build();
+        let feed = site.dir.read("site/feed.xml");
+        assert!(!feed.contains("<content "));
+        assert_eq!(elements(&feed, "summary"), ["About it"]);
+        let feed: serde_json::Value =
+            serde_json::from_str(&site.dir.read("site/feed.json")).unwrap();
+        let item = &feed["items"][0];
+        assert!(item.get("content_html").is_none());
+        assert_eq!(item["content_text"], "About it");
+        assert_eq!(item["summary"], "About it");
+    }
+
+    #[test]
+    fn posts_have_a_canonical_link() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post(
+                    "First",
+                    "canonical_url: https://example.com/original-post\n",
+                    "",
+                ),
+            ),
+            ("2020-02-01-second.md", &post("Second", "", "")),
+        ]);
+        site.build();
+        let page = site.dir.read("site/2020/01/01/first.html");
+        assert!(
+            page.contains(r#"<link rel="canonical" href="https://example.com/original-post" />"#)
+        );
+        let page = site.dir.read("site/2020/02/01/second.html");
+        assert!(page.contains(
+            r#"<link rel="canonical" href="https://blog.rust-lang.org/2020/02/01/second.html" />"#
+        ));
+        // only posts have one
+        assert!(!site
+            .dir
+            .read("site/index.html")
+            .contains(r#"rel="canonical""#));
+    }
+
+    #[test]
+    fn robots_txt_points_at_the_sitemap() {
+        let site = Site::new(&[]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        site.generator().render_robots().unwrap();
+        assert_eq!(
+            site.dir.read("site/robots.txt"),
+            "User-agent: *\nAllow: /\n\nSitemap: https://blog.rust-lang.org/sitemap.xml\n"
+        );
+
+        // no other test builds for staging
+        std::env::set_var(STAGING_VAR, "1");
+        let rendered = site.generator().render_robots();
+        std::env::remove_var(STAGING_VAR);
+        rendered.unwrap();
+        assert_eq!(
+            site.dir.read("site/robots.txt"),
+            "User-agent: *\nDisallow: /\n\nSitemap: https://blog.rust-lang.org/sitemap.xml\n"
+        );
+    }
+
+    #[test]
+    fn posts_are_rendered_with_their_layout() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "Hello")),
+            (
+                "2020-02-01-release.md",
+                &post("Release", "layout: release\n", "Hello"),
+            ),
+        ]);
+        let mut generator = site.generator();
+        generator
+            .handlebars
+            .register_template_string("release", "released: {{post.title}}")
+            .unwrap();
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        for blog in &generator.blogs {
+            generator.render_blog(blog).unwrap();
+        }
+        // posts get the `post` layout by default
+        let page = site.dir.read("site/2020/01/01/first.html");
+        assert!(page.contains(r#"<div class="post">"#), "{}", page);
+        assert_eq!(
+            site.dir.read("site/2020/02/01/release.html"),
+            "released: Release"
+        );
+    }
+
+    #[test]
+    fn feeds_are_dated_by_their_latest_post() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post("First", "updated: 2020-05-01\n", ""),
+            ),
+            ("2020-03-01-second.md", &post("Second", "", "")),
+        ]);
+        site.build();
+        let feed = site.dir.read("site/feed.xml");
+        let dates = elements(&feed, "updated");
+        for date in dates.iter().chain(&elements(&feed, "published")) {
+            let parsed = chrono::DateTime::parse_from_rfc3339(date).unwrap();
+            assert_eq!(parsed.offset().local_minus_utc(), 0, "{}", date);
+        }
+        // the latest update, even of an older post
+        assert_eq!(dates[0], "2020-05-01T00:00:00+00:00");
+        // and the same on every build
+        site.build();
+        assert_eq!(site.dir.read("site/feed.xml"), feed);
+
+        // feeds without posts are dated by the build
+        let blog = &site.generator().blogs[0];
+        std::env::set_var(SOURCE_DATE_EPOCH_VAR, "1577836800");
+        let updated = feed_updated(blog, []);
+        std::env::remove_var(SOURCE_DATE_EPOCH_VAR);
+        assert_eq!(updated, "2020-01-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn posts_are_described_in_their_meta_tags_and_feeds() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post("First", "description: Declared\n", "Some text."),
+            ),
+            ("2020-02-01-second.md", &post("Second", "", "Some text.")),
+            ("2020-03-01-third.md", &post("Third", "", "")),
+        ]);
+        site.dir.write(
+            "posts/blog.yml",
+            testing::manifest("feed-full-content: false\n"),
+        );
+        site.build();
+        for (page, description) in [
+            ("2020/01/01/first", "Declared"),
+            ("2020/02/01/second", "Some text."),
+            ("2020/03/01/third", "Posts for the tests"),
+        ] {
+            let page = site.dir.read(&format!("site/{}.html", page));
+            let tag = format!(r#"<meta name="description" content="{}">"#, description);
+            assert!(page.contains(&tag), "{} isn't in\n{}", tag, page);
+        }
+        let feed = site.dir.read("site/feed.xml");
+        assert_eq!(
+            elements(&feed, "summary"),
+            ["Posts for the tests", "Some text.", "Declared"]
+        );
+    }
+
+    #[test]
+    fn pages_reload_themselves_while_watching() {
+        let site = Site::new(&[("2020-01-01-first.md", &post("First", "", ""))]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        let generator = site.build();
+        generator.signal_live_reload().unwrap();
+        let page = site.dir.read("site/2020/01/01/first.html");
+        assert!(!page.contains("livereload.txt"));
+        assert!(!site.dir.exists("site/livereload.txt"));
+
+        let mut generator = site.generator();
+        generator.enable_live_reload().unwrap();
+        for blog in &generator.blogs {
+            generator.render_blog(blog).unwrap();
+        }
+        generator.signal_live_reload().unwrap();
+        let page = site.dir.read("site/2020/01/01/first.html");
+        assert!(page.contains(LIVE_RELOAD_SCRIPT));
+        // every build changes the version the pages compare
+        let version = site.dir.read("site/livereload.txt");
+        std::thread::sleep(Duration::from_millis(2));
+        generator.signal_live_reload().unwrap();
+        assert_ne!(site.dir.read("site/livereload.txt"), version);
+    }
+
+    #[test]
+    fn problems_are_reported_as_json() {
+        let site = Site::new(&[(
+            "2020-01-01-first.md",
+            &post(
+                "First",
+                "",
+                "[missing](/2020/01/02/missing.html) ![crab](/images/no-such-crab.png)",
+            ),
+        )]);
+        let mut generator = site.generator();
+        generator.diagnostics_file = Some(site.dir.path().join("reports/diagnostics.json"));
+        generator.check().unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&site.dir.read("reports/diagnostics.json")).unwrap();
+        let path = site.dir.path().join("posts/2020-01-01-first.md");
+        assert_eq!(
+            report,
+            json!([
+                {
+                    "code": "broken-link",
+                    "severity": "warning",
+                    "message": "broken link in 2020/01/01/first.html: /2020/01/02/missing.html",
+                    "path": path,
+                },
+                {
+                    "code": "missing-image",
+                    "severity": "warning",
+                    "message": format!(
+                        "missing image in {}: static/images/no-such-crab.png",
+                        path.display()
+                    ),
+                    "path": path,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn unlisted_blogs_are_left_out_of_site_wide_listings() {
+        let internal = testing::MANIFEST
+            .replace("The Test Blog", "The Internal Blog")
+            .replace("link-text: the test blog", "link-text: the internal blog");
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            (
+                "internal/blog.yml",
+                &format!("{}unlisted: true\n", internal),
+            ),
+            ("internal/2020-02-01-secret.md", &post("Secret", "", "")),
+        ]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        site.generator().render_pages().unwrap();
+
+        // its own pages are generated
+        assert!(site.dir.exists("site/internal/index.html"));
+        assert!(site.dir.exists("site/internal/2020/02/01/secret.html"));
+        assert!(site.dir.exists("site/internal/feed.xml"));
+        // but nothing else lists them
+        for page in [
+            "posts.json",
+            "search-index.json",
+            "all.xml",
+            "sitemap.xml",
+            "feeds.opml",
+            "index.html",
+        ] {
+            let contents = site.dir.read(&format!("site/{}", page));
+            assert!(!contents.contains("internal"), "{}:\n{}", page, contents);
+        }
+        assert!(site
+            .dir
+            .read("site/posts.json")
+            .contains("2020/01/01/first.html"));
+    }
+
+    #[test]
+    fn old_field_names_are_warned_about_on_demand() {
+        let site = Site::new(&[
+            (
+                "2020-01-01-first.md",
+                &post("First", "author: Ferris\n", ""),
+            ),
+            (
+                "2020-02-01-second.md",
+                &post("Second", "authors: Ferris\n", ""),
+            ),
+        ]);
+        let generator = site.generator();
+        generator.check().unwrap();
+        assert_eq!(generator.diagnostics.count(Severity::Warning), 0);
+
+        // no other test warns about them
+        std::env::set_var(WARN_DEPRECATED_FIELDS_VAR, "1");
+        let generator = site.generator();
+        let checked = generator.check();
+        std::env::remove_var(WARN_DEPRECATED_FIELDS_VAR);
+        checked.unwrap();
+        assert_eq!(generator.diagnostics.count(Severity::Warning), 1);
+    }
+
+    #[test]
+    fn the_opml_file_lists_the_feed_of_every_listed_blog() {
+        let inside = testing::MANIFEST.replace("title: Test Blog", "title: Inside Rust");
+        let site = Site::new(&[
+            ("inside-rust/blog.yml", &inside),
+            ("internal/blog.yml", &testing::manifest("unlisted: true\n")),
+            (
+                "no-feed/blog.yml",
+                &testing::manifest("generate-feed: false\n"),
+            ),
+        ]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        site.generator().render_opml().unwrap();
+        let opml = site.dir.read("site/feeds.opml");
+        let outlines: Vec<_> = opml
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("<outline"))
+            .collect();
+        assert_eq!(
+            outlines,
+            [
+                r#"<outline type="rss" text="Test Blog" title="Test Blog" xmlUrl="https://blog.rust-lang.org/feed.xml" htmlUrl="https://blog.rust-lang.org/"/>"#,
+                r#"<outline type="rss" text="Inside Rust" title="Inside Rust" xmlUrl="https://blog.rust-lang.org/inside-rust/feed.xml" htmlUrl="https://blog.rust-lang.org/inside-rust/"/>"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn noindex_posts_are_kept_out_of_search_engines() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            (
+                "2020-02-01-correction.md",
+                &post("Correction", "noindex: true\n", ""),
+            ),
+        ]);
+        site.build().render_sitemap().unwrap();
+        let sitemap = site.dir.read("site/sitemap.xml");
+        assert_eq!(
+            elements(&sitemap, "loc"),
+            [
+                "https://blog.rust-lang.org/",
+                "https://blog.rust-lang.org/2020/01/01/first.html",
+            ]
+        );
+
+        let robots = r#"<meta name="robots" content="noindex">"#;
+        // it's still published
+        assert!(site
+            .dir
+            .read("site/2020/02/01/correction.html")
+            .contains(robots));
+        assert!(!site.dir.read("site/2020/01/01/first.html").contains(robots));
+    }
+
+    #[test]
+    fn the_combined_feed_interleaves_the_posts_of_every_blog() {
+        let inside = testing::MANIFEST.replace("title: Test Blog", "title: Inside Rust");
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            ("2020-03-01-third.md", &post("Third", "", "")),
+            ("inside-rust/blog.yml", &inside),
+            ("inside-rust/2020-02-01-second.md", &post("Second", "", "")),
+            ("inside-rust/2020-04-01-fourth.md", &post("Fourth", "", "")),
+            ("internal/blog.yml", &testing::manifest("unlisted: true\n")),
+            ("internal/2020-05-01-secret.md", &post("Secret", "", "")),
+        ]);
+        let generator = site.generator();
+        let posts = combined_feed_posts(generator.listed_blogs(), 3).unwrap();
+        let posts: Vec<_> = posts
+            .iter()
+            .map(|post| {
+                (
+                    post["title"].as_str().unwrap(),
+                    post["blog"]["title"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            posts,
+            [
+                ("Fourth", "Inside Rust"),
+                ("Third", "Test Blog"),
+                ("Second", "Inside Rust"),
+            ]
+        );
+
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        generator.render_combined_feed().unwrap();
+        let feed = site.dir.read("site/all.xml");
+        assert_eq!(
+            elements(&feed, "id"),
+            [
+                "https://blog.rust-lang.org/all.xml",
+                "https://blog.rust-lang.org/inside-rust/2020/04/01/fourth.html",
+                "https://blog.rust-lang.org/inside-rust/",
+                "https://blog.rust-lang.org/2020/03/01/third.html",
+                "https://blog.rust-lang.org/",
+                "https://blog.rust-lang.org/inside-rust/2020/02/01/second.html",
+                "https://blog.rust-lang.org/inside-rust/",
+                "https://blog.rust-lang.org/2020/01/01/first.html",
+                "https://blog.rust-lang.org/",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_404_page_is_styled_like_the_others() {
+        let site = Site::new(&[(
+            "inside-rust/blog.yml",
+            &testing::manifest("not-found-html: Not here.\n"),
+        )]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        site.generator().render_not_found().unwrap();
+        let page = site.dir.read("site/404.html");
+        for marker in [
+            "<title>Page not found | Test Blog</title>",
+            "<nav ",
+            "<h2>Page not found</h2>",
+            "<p>The page you were looking for doesn't exist, or it has moved.</p>",
+            // it's served for any url, so its links start at the root
+            r#"<a href="/">Back to The Test Blog</a>"#,
+            r#"href="/styles/app.css"#,
+        ] {
+            assert!(page.contains(marker), "{} isn't in\n{}", marker, page);
+        }
+
+        // the message is the one of the blog at the root, and links follow
+        // where the site is hosted
+        site.dir.write(
+            "posts/blog.yml",
+            testing::manifest("not-found-html: Gone <em>fishing</em>.\n"),
+        );
+        let mut generator = site.generator();
+        generator.config.base_url = "https://example.com/blog/".into();
+        generator.render_not_found().unwrap();
+        let page = site.dir.read("site/404.html");
+        assert!(page.contains("<p>Gone <em>fishing</em>.</p>"), "{}", page);
+        assert!(
+            page.contains(r#"<a href="/blog/">Back to The Test Blog</a>"#),
+            "{}",
+            page
+        );
+    }
+
+    #[test]
+    fn posts_with_directory_permalinks_are_index_pages() {
+        let site = Site::new(&[
+            (
+                "blog.yml",
+                &testing::manifest("permalink: \"/:year/:slug/\"\n"),
+            ),
+            ("2020-01-02-first.md", &post("First", "", "")),
+        ]);
+        site.build();
+        let page = site.dir.read("site/2020/first/index.html");
+        assert!(page
+            .contains(r#"<link rel="canonical" href="https://blog.rust-lang.org/2020/first/" />"#));
+        // with links back to the root from the directory
+        assert!(page.contains(r#"href="../../styles/app.css"#), "{}", page);
+    }
+
+    #[test]
+    fn blogs_can_go_without_feeds() {
+        let changelog = testing::MANIFEST
+            .replace("The Test Blog", "The Changelog")
+            .replace("link-text: the test blog", "link-text: the changelog");
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            (
+                "changelog/blog.yml",
+                &format!("{}generate-feed: false\n", changelog),
+            ),
+            (
+                "changelog/2020-02-01-release.md",
+                &post("Release", "tags: [release]\n", ""),
+            ),
+        ]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        let generator = site.build();
+        generator.render_pages().unwrap();
+
+        // the pages are still generated
+        assert!(site.dir.exists("site/changelog/index.html"));
+        assert!(site.dir.exists("site/changelog/tags/release/index.html"));
+        // but not the feeds
+        for feed in ["feed.xml", "feed.json", "tags/release/feed.xml"] {
+            assert!(
+                !site.dir.exists(&format!("site/changelog/{}", feed)),
+                "{}",
+                feed
+            );
+        }
+        let index = site.dir.read("site/changelog/index.html");
+        assert!(!index.contains("application/atom+xml"), "{}", index);
+        assert!(!index.contains("application/feed+json"), "{}", index);
+        for page in ["all.xml", "feeds.opml"] {
+            let contents = site.dir.read(&format!("site/{}", page));
+            assert!(!contents.contains("changelog"), "{}:\n{}", page, contents);
+        }
+
+        // while the other blogs keep theirs
+        assert!(site.dir.exists("site/feed.xml"));
+        assert!(site.dir.exists("site/feed.json"));
+        assert!(site
+            .dir
+            .read("site/index.html")
+            .contains("application/atom+xml"));
+    }
+
+    #[test]
+    fn posts_without_an_image_are_shared_with_the_preview_image_of_their_blog() {
+        let site = Site::new(&[
+            (
+                "blog.yml",
+                &testing::manifest("preview-image: /images/card.png\n"),
+            ),
+            ("2020-01-01-first.md", &post("First", "", "")),
+            (
+                "2020-02-01-second.md",
+                &post("Second", "image: /images/second.png\n", ""),
+            ),
+        ]);
+        site.build();
+        for (page, image) in [
+            ("site/2020/01/01/first.html", "card.png"),
+            ("site/2020/02/01/second.html", "second.png"),
+        ] {
+            let page = site.dir.read(page);
+            for tag in [
+                format!(
+                    r#"<meta property="og:image" content="https://blog.rust-lang.org/images/{}" />"#,
+                    image
+                ),
+                format!(
+                    r#"<meta name="twitter:image" content="https://blog.rust-lang.org/images/{}">"#,
+                    image
+                ),
+                r#"<meta name="twitter:card" content="summary_large_image">"#.to_string(),
+            ] {
+                assert!(page.contains(&tag), "{} isn't in\n{}", tag, page);
+            }
+            assert_eq!(
+                json_ld(&page)["image"],
+                format!("https://blog.rust-lang.org/images/{}", image)
+            );
+        }
+    }
+
+    #[test]
+    fn blogs_can_have_stylesheets_of_their_own() {
+        let changelog = testing::MANIFEST
+            .replace("The Test Blog", "The Changelog")
+            .replace("link-text: the test blog", "link-text: the changelog");
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            (
+                "changelog/blog.yml",
+                &format!("{}extra-styles: [styles/changelog.css]\n", changelog),
+            ),
+            ("changelog/styles/changelog.css", "h1 { color: red }"),
+            ("changelog/2020-02-01-release.md", &post("Release", "", "")),
+        ]);
+        site.build();
+
+        assert_eq!(
+            site.dir.read("site/changelog/styles/changelog.css"),
+            "h1 { color: red }"
+        );
+        for (page, link) in [
+            (
+                "site/changelog/index.html",
+                "../changelog/styles/changelog.css",
+            ),
+            (
+                "site/changelog/2020/02/01/release.html",
+                "../../../../changelog/styles/changelog.css",
+            ),
+        ] {
+            let page = site.dir.read(page);
+            let tag = format!(r#"<link rel="stylesheet" href="{}"/>"#, link);
+            assert!(page.contains(&tag), "{} isn't in\n{}", tag, page);
+        }
+        for page in ["site/index.html", "site/2020/01/01/first.html"] {
+            let page = site.dir.read(page);
+            assert!(!page.contains("changelog.css"), "{}", page);
+        }
+    }
+
+    #[test]
+    fn pages_which_didnt_change_arent_written_again() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "Hello")),
+            ("2020-02-01-second.md", &post("Second", "", "Hello")),
+        ]);
+        site.build().remove_stale(false).unwrap();
+        // date the pages back, to tell which ones are written again
+        let long_ago = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let modified = |page: &str| {
+            fs::metadata(site.dir.path().join(page))
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        for page in ["site/2020/01/01/first.html", "site/2020/02/01/second.html"] {
+            fs::File::options()
+                .write(true)
+                .open(site.dir.path().join(page))
+                .unwrap()
+                .set_modified(long_ago)
+                .unwrap();
+        }
+        fs::remove_file(site.dir.path().join("site/index.html")).unwrap();
+
+        site.dir
+            .write("posts/2020-01-01-first.md", post("First", "", "Hi"));
+        site.build().remove_stale(false).unwrap();
+        assert_ne!(modified("site/2020/01/01/first.html"), long_ago);
+        assert!(site.dir.read("site/2020/01/01/first.html").contains("Hi"));
+        assert_eq!(modified("site/2020/02/01/second.html"), long_ago);
+        // files which went missing are written again, even if they didn't change
+        assert!(site.dir.exists("site/index.html"));
+    }
+
+    #[test]
+    fn posts_link_to_where_they_can_be_edited() {
+        let site = Site::new(&[("2020-01-01-first.md", &post("First", "", ""))]);
+        site.build();
+        let page = site.dir.read("site/2020/01/01/first.html");
+        assert!(!page.contains("post-edit"), "{}", page);
+
+        let path = site.dir.path();
+        let config = Config {
+            edit_url_base: Some("https://github.com/rust-lang/blog/edit/master/".into()),
+            ..Config::default()
+        };
+        let generator = Generator::new(
+            config,
+            path.join("site"),
+            path.join("posts"),
+            path.join("cache"),
+        )
+        .unwrap();
+        for blog in &generator.blogs {
+            generator.render_blog(blog).unwrap();
+        }
+        let page = site.dir.read("site/2020/01/01/first.html");
+        let start = page.find(r#"<p class="post-edit"><a href=""#).unwrap();
+        let link = page[start..].lines().next().unwrap();
+        // the source path is relative to where the site is generated from,
+        // which is a temporary directory here
+        assert!(
+            link.starts_with(
+                r#"<p class="post-edit"><a href="https://github.com/rust-lang/blog/edit/master/"#
+            ),
+            "{}",
+            link
+        );
+        assert!(
+            link.ends_with(r#"/posts/2020-01-01-first.md">Edit this post</a></p>"#),
+            "{}",
+            link
+        );
+    }
+
+    #[test]
+    fn polling_a_missing_posts_directory_fails() {
+        let site = Site::new(&[("2020-01-01-first.md", &post("First", "", ""))]);
+        let times = modified_times(&site.dir.path().join("posts")).unwrap();
+        assert_eq!(times.len(), 2);
+        // rather than reporting every file as removed
+        let err = modified_times(&site.dir.path().join("gone")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn reloading_removes_the_pages_which_arent_generated_anymore() {
+        let site = Site::new(&[
+            ("2020-01-01-first.md", &post("First", "", "")),
+            ("2020-02-01-second.md", &post("Second", "", "")),
+            ("inside-rust/blog.yml", testing::MANIFEST),
+            ("inside-rust/2020-01-01-inside.md", &post("Inside", "", "")),
+        ]);
+        fs::create_dir_all(site.dir.path().join("site")).unwrap();
+        let mut generator = site.generator();
+        generator.render_pages().unwrap();
+        generator.remove_stale(false).unwrap();
+        assert!(site.dir.exists("site/2020/02/01/second.html"));
+        assert!(site.dir.exists("site/inside-rust/2020/01/01/inside.html"));
+
+        // remove a post, and change what the 404 page says
+        let posts = site.dir.path().join("posts");
+        fs::remove_file(posts.join("2020-02-01-second.md")).unwrap();
+        site.dir.write(
+            "posts/blog.yml",
+            testing::manifest("not-found-html: <p>Nothing here.</p>\n"),
+        );
+        generator
+            .reload(&[posts.join("2020-02-01-second.md"), posts.join("blog.yml")])
+            .unwrap();
+        assert!(!site.dir.exists("site/2020/02/01/second.html"));
+        assert!(!site.dir.exists("site/2020/02"));
+        assert!(site.dir.exists("site/2020/01/01/first.html"));
+        assert!(site.dir.exists("site/inside-rust/2020/01/01/inside.html"));
+        assert!(site
+            .dir
+            .read("site/404.html")
+            .contains("<p>Nothing here.</p>"));
+
+        // and the pages of removed blogs go too
+        fs::remove_dir_all(posts.join("inside-rust")).unwrap();
+        generator
+            .reload(&[
+                posts.join("inside-rust/blog.yml"),
+                posts.join("inside-rust/2020-01-01-inside.md"),
+            ])
+            .unwrap();
+        assert!(!site.dir.exists("site/inside-rust"));
+        assert!(site.dir.exists("site/index.html"));
+        assert!(site.dir.exists("site/2020/01/01/first.html"));
+    }
 }
@@ -0,0 +1,416 @@
+use super::assets;
+use super::blogs::Blog;
+use super::images;
+use regex::{Captures, Regex};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A link in a post that doesn't lead to anything on the generated site.
+#[derive(Debug)]
+pub(crate) struct BrokenLink {
+    /// The path of the post on the site.
+    pub(crate) post: String,
+    /// The source file of the post.
+    pub(crate) path: PathBuf,
+    pub(crate) link: String,
+}
+
+/// An image used by a post that isn't among the static assets.
+#[derive(Debug)]
+pub(crate) struct MissingImage {
+    /// The source file of the post.
+    pub(crate) post: PathBuf,
+    pub(crate) file: PathBuf,
+}
+
+/// Check every internal link of the rendered posts, returning the ones that
+/// resolve neither to a post nor to a file `exists` says is on the site. It's
+/// given the path of the file relative to the root of the site.
+pub(crate) fn find_broken(blogs: &[Blog], exists: impl Fn(&str) -> bool) -> Vec<BrokenLink> {
+    lazy_static::lazy_static! {
+        static ref R: Regex = Regex::new(r#"\shref="([^"]*)""#).unwrap();
+    }
+
+    let mut known: HashSet<String> = HashSet::new();
+    for blog in blogs {
+        known.insert(format!("{}index.html", blog.url_prefix()));
+        for post in blog.posts() {
+            let url = format!("{}{}", blog.url_prefix(), post.url);
+            // links to posts with a directory as url resolve to its index
+            if url.ends_with('/') {
+                known.insert(format!("{}index.html", url));
+            } else {
+                known.insert(url);
+            }
+        }
+    }
+
+    let mut broken = Vec::new();
+    for blog in blogs {
+        for post in blog.posts() {
+            let source = format!("{}{}", blog.url_prefix(), post.url);
+            for caps in R.captures_iter(&post.contents) {
+                let link = &caps[1];
+                let target = match resolve(blog.base_url(), &source, link) {
+                    Some(target) => target,
+                    None => continue,
+                };
+                if known.contains(&target) || exists(&target) {
+                    continue;
+                }
+                broken.push(BrokenLink {
+                    post: source.clone(),
+                    path: post.path.clone(),
+                    link: link.to_string(),
+                });
+            }
+        }
+    }
+    broken
+}
+
+/// Check that the local images of the posts, including their preview image,
+/// exist in the directory the static assets are copied from.
+pub(crate) fn find_missing_images(blogs: &[Blog], static_directory: &Path) -> Vec<MissingImage> {
+    lazy_static::lazy_static! {
+        static ref R: Regex = Regex::new(r#"<img\s[^>]*?src="([^"]*)""#).unwrap();
+    }
+
+    let mut missing = Vec::new();
+    for blog in blogs {
+        for post in blog.posts() {
+            let source = format!("{}{}", blog.url_prefix(), post.url);
+            let images = R
+                .captures_iter(&post.contents)
+                .map(|caps| caps.get(1).unwrap().as_str())
+                .chain(post.preview_image.as_deref());
+            for image in images {
+                let file = match resolve(blog.base_url(), &source, image) {
+                    Some(target) => static_directory.join(target),
+                    None => continue,
+                };
+                if !file.is_file() {
+                    missing.push(MissingImage {
+                        post: post.path.clone(),
+                        file,
+                    });
+                }
+            }
+        }
+    }
+    missing
+}
+
+/// Give the local images of the html of the page at `source` the `width` and
+/// `height` of their file in `static_directory`, so the page doesn't shift as
+/// they load, and have them loaded lazily. Images whose size can't be read,
+/// like missing ones, are left alone. Attributes already there are kept.
+pub(crate) fn add_image_dimensions(
+    html: &str,
+    base_url: &str,
+    source: &str,
+    static_directory: &Path,
+) -> String {
+    lazy_static::lazy_static! {
+        static ref IMG: Regex = Regex::new(r#"<img\s[^>]*?src="([^"]*)"[^>]*?(/?>)"#).unwrap();
+        static ref WIDTH: Regex = Regex::new(r#"\swidth="#).unwrap();
+        static ref HEIGHT: Regex = Regex::new(r#"\sheight="#).unwrap();
+        static ref LOADING: Regex = Regex::new(r#"\sloading="#).unwrap();
+    }
+    IMG.replace_all(html, |caps: &Captures| {
+        let tag = &caps[0];
+        let dimensions = resolve(base_url, source, &caps[1])
+            .and_then(|target| images::dimensions(&static_directory.join(target)));
+        let (width, height) = match dimensions {
+            Some(dimensions) => dimensions,
+            None => return tag.to_string(),
+        };
+        let end = caps.get(2).unwrap().start() - caps.get(0).unwrap().start();
+        let mut rewritten = tag[..end].trim_end().to_string();
+        if !WIDTH.is_match(tag) && !HEIGHT.is_match(tag) {
+            rewritten.push_str(&format!(r#" width="{}" height="{}""#, width, height));
+        }
+        if !LOADING.is_match(tag) {
+            rewritten.push_str(r#" loading="lazy""#);
+        }
+        match &caps[2] {
+            "/>" => format!("{} />", rewritten),
+            _ => format!("{}>", rewritten),
+        }
+    })
+    .into_owned()
+}
+
+/// Add the hash of their content to the links and image sources of the html of
+/// the page at `source` which lead to a file in `static_directory`, as an `h`
+/// query parameter. Links with a query of their own are left alone.
+pub(crate) fn add_asset_hashes(
+    html: &str,
+    base_url: &str,
+    source: &str,
+    static_directory: &Path,
+) -> String {
+    lazy_static::lazy_static! {
+        static ref R: Regex = Regex::new(r#"(\s(?:href|src)=")([^"?]*)""#).unwrap();
+    }
+    R.replace_all(html, |caps: &Captures| {
+        let link = &caps[2];
+        let hash = resolve(base_url, source, link)
+            .and_then(|target| assets::content_hash(&static_directory.join(target)));
+        match (hash, link.find('#')) {
+            (Some(hash), Some(i)) => {
+                format!("{}{}?h={}{}\"", &caps[1], &link[..i], hash, &link[i..])
+            }
+            (Some(hash), None) => format!("{}{}?h={}\"", &caps[1], link, hash),
+            (None, _) => caps[0].to_string(),
+        }
+    })
+    .into_owned()
+}
+
+/// Rewrite the links and image sources of the html of the page at `source` to
+/// absolute urls below `base_url`, for places like feeds where relative ones
+/// can't be relied on.
+pub(crate) fn make_absolute(html: &str, base_url: &str, source: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref R: Regex = Regex::new(r#"(\s(?:href|src)=")([^"]*)""#).unwrap();
+    }
+    R.replace_all(html, |caps: &Captures| {
+        let link = &caps[2];
+        let absolute = match link.find(['#', '?']) {
+            // a link within the page itself
+            Some(0) => Some(format!("{}{}{}", base_url, source, link)),
+            Some(i) => resolve(base_url, source, link)
+                .map(|path| format!("{}{}{}", base_url, path, &link[i..])),
+            None => resolve(base_url, source, link).map(|path| format!("{}{}", base_url, path)),
+        };
+        format!("{}{}\"", &caps[1], absolute.as_deref().unwrap_or(link))
+    })
+    .into_owned()
+}
+
+/// Move the root-relative links and image sources of the contents of a post
+/// below the `prefix` of its blog, which is either empty or ends with a `/`:
+/// `/images/x.png` in a post of `inside-rust/` is
+/// `/inside-rust/images/x.png`. Links which are already below the prefix are
+/// left alone, like absolute urls.
+pub(crate) fn add_prefix(html: &str, prefix: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref R: Regex = Regex::new(r#"(\s(?:href|src)=")/([^/"][^"]*)?""#).unwrap();
+    }
+    if prefix.is_empty() {
+        return html.to_string();
+    }
+    R.replace_all(html, |caps: &Captures| {
+        let path = caps.get(2).map_or("", |path| path.as_str());
+        if path.starts_with(prefix) || path == prefix.trim_end_matches('/') {
+            caps[0].to_string()
+        } else {
+            format!("{}/{}{}\"", &caps[1], prefix, path)
+        }
+    })
+    .into_owned()
+}
+
+/// Rewrite root-relative links and image sources of html to be relative to the
+/// page, whose way back to the root of the site is `root`. The site then works
+/// wherever it is hosted, and however deep the blog of the page is. Those of
+/// posts are already below the prefix of their blog, see `add_prefix`.
+pub(crate) fn make_relative(html: &str, root: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref R: Regex = Regex::new(r#"(\s(?:href|src)=")/([^/"][^"]*)?""#).unwrap();
+    }
+    R.replace_all(html, |caps: &Captures| {
+        let path = caps.get(2).map_or("", |path| path.as_str());
+        format!("{}{}{}\"", &caps[1], root, path)
+    })
+    .into_owned()
+}
+
+/// Resolve a link found in the page at `source` to a file path relative to the
+/// root of the site hosted at `base_url`, or `None` for external links.
+fn resolve(base_url: &str, source: &str, link: &str) -> Option<String> {
+    let link = match link.strip_prefix(base_url) {
+        Some(rest) => format!("/{}", rest),
+        None => link.to_string(),
+    };
+    let link = link.split(['#', '?']).next().unwrap();
+    if link.is_empty() || link.starts_with("//") {
+        return None;
+    }
+    // anything with a scheme (`https:`, `mailto:`, ...) points somewhere else
+    if let Some(colon) = link.find(':') {
+        if !link[..colon].contains('/') {
+            return None;
+        }
+    }
+
+    let mut segments: Vec<&str> = if link.starts_with('/') {
+        Vec::new()
+    } else {
+        // relative to the directory of the page
+        let mut segments: Vec<_> = source.split('/').collect();
+        segments.pop();
+        segments
+    };
+    for segment in link.split('/') {
+        match segment {
+            "" | "." => {}
+            // like browsers do, going up from the root stays at the root
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if link.ends_with('/') || segments.is_empty() {
+        segments.push("index.html");
+    }
+    Some(segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{self, load_blog, post, TempDir};
+    use super::super::vfs::MemoryFs;
+    use super::*;
+
+    #[test]
+    fn root_relative_links_are_moved_below_the_prefix() {
+        let html = r#"<img src="/images/x.png" /> <a href="/inside-rust/2020/01/01/x.html">x</a> <a href="/">home</a> <a href="https://example.com/y">y</a> <img src="//example.com/z.png" />"#;
+        assert_eq!(
+            add_prefix(html, "inside-rust/"),
+            r#"<img src="/inside-rust/images/x.png" /> <a href="/inside-rust/2020/01/01/x.html">x</a> <a href="/inside-rust/">home</a> <a href="https://example.com/y">y</a> <img src="//example.com/z.png" />"#
+        );
+        assert_eq!(add_prefix(html, ""), html);
+    }
+
+    #[test]
+    fn root_relative_links_are_made_relative_to_the_page() {
+        let html = add_prefix(r#"<img src="/images/x.png" />"#, "inside-rust/");
+        assert_eq!(
+            make_relative(&html, "../../../../"),
+            r#"<img src="../../../../inside-rust/images/x.png" />"#
+        );
+    }
+
+    #[test]
+    fn only_links_to_nothing_are_broken() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            ("posts/2020-01-01-first.md", &*post("First", "", "")),
+            (
+                "posts/2020-02-01-second.md",
+                &*post(
+                    "Second",
+                    "",
+                    "[first](../../../2020/01/01/first.html#intro) [missing](/2020/01/02/missing.html) \
+                     [logo](/images/logo.png) [elsewhere](https://www.rust-lang.org/) [index](/)",
+                ),
+            ),
+        ]);
+        let blogs = [load_blog(&fs).unwrap()];
+        let broken = find_broken(&blogs, |target| target == "images/logo.png");
+        let broken: Vec<_> = broken
+            .iter()
+            .map(|link| (&*link.post, &*link.path, &*link.link))
+            .collect();
+        assert_eq!(
+            broken,
+            [(
+                "2020/02/01/second.html",
+                Path::new("posts/2020-02-01-second.md"),
+                "/2020/01/02/missing.html"
+            )]
+        );
+    }
+
+    #[test]
+    fn only_local_images_which_arent_there_are_missing() {
+        let fs = MemoryFs::new([
+            ("posts/blog.yml", testing::MANIFEST),
+            (
+                "posts/2020-01-01-first.md",
+                &*post(
+                    "First",
+                    "image: /images/preview.png\n",
+                    "![there](/images/there.png) ![missing](/images/missing.png) \
+                     ![remote](https://www.rust-lang.org/logo.png)",
+                ),
+            ),
+        ]);
+        let blogs = [load_blog(&fs).unwrap()];
+        let assets = TempDir::new();
+        assets.write("images/there.png", "");
+        assets.write("images/preview.png", "");
+
+        let missing = find_missing_images(&blogs, assets.path());
+        let missing: Vec<_> = missing
+            .iter()
+            .map(|image| (&*image.post, image.file.clone()))
+            .collect();
+        assert_eq!(
+            missing,
+            [(
+                Path::new("posts/2020-01-01-first.md"),
+                assets.path().join("images/missing.png")
+            )]
+        );
+    }
+
+    #[test]
+    fn links_in_feeds_are_made_absolute() {
+        let html = r##"<a href="../../02/01/second.html">a</a> <a href="#top">b</a> <a href="other.html?x=1#y">c</a> <img src="/images/crab.png" /> <a href="https://example.com/">d</a> <a href="mailto:ferris@example.com">e</a>"##;
+        assert_eq!(
+            make_absolute(
+                html,
+                "https://blog.rust-lang.org/",
+                "inside-rust/2020/01/01/first.html"
+            ),
+            r##"<a href="https://blog.rust-lang.org/inside-rust/2020/02/01/second.html">a</a> <a href="https://blog.rust-lang.org/inside-rust/2020/01/01/first.html#top">b</a> <a href="https://blog.rust-lang.org/inside-rust/2020/01/01/other.html?x=1#y">c</a> <img src="https://blog.rust-lang.org/images/crab.png" /> <a href="https://example.com/">d</a> <a href="mailto:ferris@example.com">e</a>"##
+        );
+    }
+
+    #[test]
+    fn images_get_their_dimensions_and_are_loaded_lazily() {
+        let dir = TempDir::new();
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(640u32.to_be_bytes());
+        png.extend(480u32.to_be_bytes());
+        dir.write("images/crab.png", png);
+
+        let html = r#"<img src="/images/crab.png" alt="a crab" /> <img src="../../../images/crab.png"> <img src="/images/crab.png" width="320" loading="eager"> <img src="/images/missing.png" />"#;
+        assert_eq!(
+            add_image_dimensions(
+                html,
+                "https://blog.rust-lang.org/",
+                "2020/01/01/first.html",
+                dir.path()
+            ),
+            r#"<img src="/images/crab.png" alt="a crab" width="640" height="480" loading="lazy" /> <img src="../../../images/crab.png" width="640" height="480" loading="lazy"> <img src="/images/crab.png" width="320" loading="eager"> <img src="/images/missing.png" />"#
+        );
+    }
+
+    #[test]
+    fn asset_links_carry_the_hash_of_their_content() {
+        let dir = TempDir::new();
+        dir.write("images/crab.png", "a");
+        let hashed = |dir: &TempDir| {
+            let html = r#"<img src="/images/crab.png" /> <a href="../../../images/crab.png#top">crab</a> <a href="/images/crab.png?v=1">crab</a> <img src="/images/missing.png" />"#;
+            add_asset_hashes(
+                html,
+                "https://blog.rust-lang.org/",
+                "2020/01/01/first.html",
+                dir.path(),
+            )
+        };
+        assert_eq!(
+            hashed(&dir),
+            r#"<img src="/images/crab.png?h=af63dc4c" /> <a href="../../../images/crab.png?h=af63dc4c#top">crab</a> <a href="/images/crab.png?v=1">crab</a> <img src="/images/missing.png" />"#
+        );
+        assert_eq!(hashed(&dir), hashed(&dir));
+
+        dir.write("images/crab.png", "b");
+        assert!(!hashed(&dir).contains("af63dc4c"));
+    }
+}
@@ -0,0 +1,540 @@
+use super::math::Math;
+use super::slugs::Slugs;
+use comrak::nodes::{Ast, AstNode, NodeCode, NodeHtmlBlock, NodeValue};
+use comrak::{Arena, ComrakOptions, ComrakPlugins};
+use regex::{Captures, Regex};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// A heading of a post, as listed in its table of contents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TocEntry {
+    pub(crate) level: u32,
+    pub(crate) text: String,
+    pub(crate) slug: String,
+}
+
+/// The result of rendering a markdown document.
+pub(crate) struct Rendered {
+    pub(crate) html: String,
+    pub(crate) toc: Vec<TocEntry>,
+    /// The text of the first paragraph, without any markup.
+    pub(crate) first_paragraph: String,
+    /// The text of the whole document, without any markup and with runs of
+    /// whitespace collapsed to a single space.
+    pub(crate) text: String,
+}
+
+/// Render markdown to html, also collecting the table of contents and the
+/// first paragraph of the document. Headings are moved `heading_shift` levels
+/// down, but not past `<h6>`. With `math`, TeX between dollars is turned into
+/// the markup KaTeX renders. Without `allow_html`, raw html in the document is
+/// escaped, showing up as text.
+pub(crate) fn render(
+    md: &str,
+    options: &ComrakOptions,
+    plugins: &ComrakPlugins,
+    heading_shift: u32,
+    math: bool,
+    allow_html: bool,
+) -> Rendered {
+    let (md, math) = if math {
+        Math::extract(md)
+    } else {
+        (md.to_string(), Math::none())
+    };
+    let arena = Arena::new();
+    let root = parse(&arena, &md, options, allow_html);
+
+    let mut headings = Vec::new();
+    let mut first_paragraph = None;
+    for node in root.descendants() {
+        if let NodeValue::Heading(ref mut heading) = node.data.borrow_mut().value {
+            heading.level = (heading.level + heading_shift).min(6);
+        }
+        match node.data.borrow().value {
+            NodeValue::Heading(ref heading) => {
+                headings.push((node, heading.level, math.restore_text(&text_of(node))));
+            }
+            NodeValue::Paragraph if first_paragraph.is_none() => {
+                first_paragraph = Some(math.restore_text(&text_of(node)));
+            }
+            _ => {}
+        }
+    }
+
+    // Smart punctuation turns `--` into `–` and `---` into `—`, which slugify
+    // would drop, while anchors have always kept them as hyphens. The slugs of
+    // those headings are made from the document parsed without it.
+    let has_smart_dashes = options.parse.smart
+        && headings
+            .iter()
+            .any(|(_, _, text)| text.contains(['\u{2013}', '\u{2014}']));
+    let written: Option<Vec<String>> = has_smart_dashes.then(|| {
+        let mut options = options.clone();
+        options.parse.smart = false;
+        parse(&arena, &md, &options, allow_html)
+            .descendants()
+            .filter(|node| matches!(node.data.borrow().value, NodeValue::Heading(_)))
+            .map(|node| math.restore_text(&text_of(node)))
+            .collect()
+    });
+
+    let mut slugs = Slugs::default();
+    let mut toc = Vec::new();
+    for (i, (heading, level, text)) in headings.into_iter().enumerate() {
+        let slug = slugs.unique(written.as_ref().map_or(&text, |written| &written[i]));
+        // The anchors comrak would add with `header_ids`, but with the slugs
+        // of the table of contents.
+        let anchor = format!(
+            r##"<a href="#{}" aria-hidden="true" class="anchor" id="{}"></a>"##,
+            slug, slug
+        );
+        heading.prepend(
+            arena.alloc(AstNode::new(RefCell::new(Ast::new(NodeValue::HtmlInline(
+                anchor.into_bytes(),
+            ))))),
+        );
+        toc.push(TocEntry { level, slug, text });
+    }
+
+    let mut text = String::new();
+    collect_plain_text(root, &mut text);
+    let text = math.restore_text(&text);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let mut html = Vec::new();
+    comrak::format_html_with_plugins(root, options, &mut html, plugins).unwrap();
+    let html = math.restore_html(&String::from_utf8(html).unwrap());
+    Rendered {
+        html: dedup_footnote_refs(&html),
+        toc,
+        first_paragraph: first_paragraph.unwrap_or_default(),
+        text,
+    }
+}
+
+/// Parse the document, with everything done to it before it is rendered which
+/// changes its text.
+fn parse<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    md: &str,
+    options: &ComrakOptions,
+    allow_html: bool,
+) -> &'a AstNode<'a> {
+    let root = comrak::parse_document(arena, md, options);
+    if !allow_html {
+        escape_html(arena, root);
+    }
+    render_admonitions(arena, root);
+    expand_emoji(root);
+    root
+}
+
+/// Turn the raw html of the document into text. It has to be done before any
+/// html of our own, like the anchors of headings, is added to it, which is why
+/// comrak's `escape` option can't be used.
+fn escape_html<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) {
+    for node in root.descendants() {
+        let mut data = node.data.borrow_mut();
+        match data.value {
+            NodeValue::HtmlInline(ref literal) => data.value = NodeValue::Text(literal.clone()),
+            NodeValue::HtmlBlock(ref block) => {
+                let mut literal = block.literal.clone();
+                while literal.last() == Some(&b'\n') {
+                    literal.pop();
+                }
+                data.value = NodeValue::Paragraph;
+                node.append(
+                    arena.alloc(AstNode::new(RefCell::new(Ast::new(NodeValue::Text(
+                        literal,
+                    ))))),
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The kinds of GitHub-style alerts, as written in the marker, and their title.
+static ADMONITIONS: &[(&str, &str)] = &[
+    ("NOTE", "Note"),
+    ("TIP", "Tip"),
+    ("IMPORTANT", "Important"),
+    ("WARNING", "Warning"),
+    ("CAUTION", "Caution"),
+];
+
+/// Turn blockquotes starting with a `[!NOTE]`-style marker into admonition
+/// containers. Blockquotes with an unknown marker are left alone.
+fn render_admonitions<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) {
+    let quotes: Vec<_> = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::BlockQuote))
+        .collect();
+    for quote in quotes {
+        let paragraph = match quote.first_child() {
+            Some(paragraph) if matches!(paragraph.data.borrow().value, NodeValue::Paragraph) => {
+                paragraph
+            }
+            _ => continue,
+        };
+        let marker = match paragraph.first_child() {
+            Some(marker) => marker,
+            None => continue,
+        };
+        let kind = match marker.data.borrow().value {
+            NodeValue::Text(ref text) => admonition_kind(text),
+            _ => None,
+        };
+        let (class, title) = match kind {
+            Some(kind) => kind,
+            None => continue,
+        };
+        // the marker has to be on a line of its own
+        if let Some(next) = marker.next_sibling() {
+            match next.data.borrow().value {
+                NodeValue::SoftBreak | NodeValue::LineBreak => {}
+                _ => continue,
+            }
+            next.detach();
+        }
+        marker.detach();
+        if paragraph.first_child().is_none() {
+            paragraph.detach();
+        }
+
+        let open = format!(
+            "<div class=\"admonition admonition-{}\">\n<p class=\"admonition-title\">{}</p>\n",
+            class.to_lowercase(),
+            title
+        );
+        quote.insert_before(html_block(arena, open));
+        while let Some(child) = quote.first_child() {
+            quote.insert_before(child);
+        }
+        quote.insert_before(html_block(arena, "</div>\n".to_string()));
+        quote.detach();
+    }
+}
+
+fn admonition_kind(text: &[u8]) -> Option<(&'static str, &'static str)> {
+    let text = std::str::from_utf8(text).ok()?.trim();
+    let kind = text.strip_prefix("[!")?.strip_suffix(']')?;
+    ADMONITIONS
+        .iter()
+        .find(|(marker, _)| marker.eq_ignore_ascii_case(kind))
+        .copied()
+}
+
+fn html_block<'a>(arena: &'a Arena<AstNode<'a>>, html: String) -> &'a AstNode<'a> {
+    arena.alloc(AstNode::new(RefCell::new(Ast::new(NodeValue::HtmlBlock(
+        NodeHtmlBlock {
+            block_type: 0,
+            literal: html.into_bytes(),
+        },
+    )))))
+}
+
+/// Replace `:tada:`-style shortcodes in the text of the document with the emoji
+/// they stand for. Unknown shortcodes, and anything in code, are left alone.
+fn expand_emoji<'a>(root: &'a AstNode<'a>) {
+    lazy_static::lazy_static! {
+        static ref R: Regex = Regex::new(r":([a-z0-9_+-]+):").unwrap();
+    }
+    for node in root.descendants() {
+        if let NodeValue::Text(ref mut literal) = node.data.borrow_mut().value {
+            let text = String::from_utf8_lossy(literal);
+            let expanded = R.replace_all(&text, |caps: &Captures| {
+                match emojis::get_by_shortcode(&caps[1]) {
+                    Some(emoji) => emoji.as_str().to_string(),
+                    None => caps[0].to_string(),
+                }
+            });
+            if expanded != text {
+                *literal = expanded.into_owned().into_bytes();
+            }
+        }
+    }
+}
+
+/// Comrak gives every reference to the same footnote the same id, so give the
+/// repeated ones a suffix to keep the ids in the page unique. The backlink in
+/// the footnote keeps pointing at the first reference.
+fn dedup_footnote_refs(html: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref R: Regex = Regex::new(r##"<a href="#fn(\d+)" id="fnref(\d+)">"##).unwrap();
+    }
+    let mut seen = HashMap::new();
+    R.replace_all(html, |caps: &Captures| {
+        let count = seen.entry(caps[2].to_string()).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            caps[0].to_string()
+        } else {
+            format!(
+                r##"<a href="#fn{}" id="fnref{}-{}">"##,
+                &caps[1], &caps[2], count
+            )
+        }
+    })
+    .into_owned()
+}
+
+fn text_of<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = Vec::new();
+    collect_text(node, &mut text);
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+/// Collect the text of a node the same way comrak does for heading ids, which
+/// the slugs of headings are made from.
+fn collect_text<'a>(node: &'a AstNode<'a>, output: &mut Vec<u8>) {
+    match node.data.borrow().value {
+        NodeValue::Text(ref literal) | NodeValue::Code(NodeCode { ref literal, .. }) => {
+            output.extend_from_slice(literal)
+        }
+        NodeValue::LineBreak | NodeValue::SoftBreak => output.push(b' '),
+        _ => {
+            for child in node.children() {
+                collect_text(child, output);
+            }
+        }
+    }
+}
+
+/// Collect all the text of a node, including code and the text of raw html.
+/// Blocks are separated by whitespace.
+fn collect_plain_text<'a>(node: &'a AstNode<'a>, output: &mut String) {
+    lazy_static::lazy_static! {
+        static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    }
+    let value = &node.data.borrow().value;
+    match value {
+        NodeValue::Text(ref literal) | NodeValue::Code(NodeCode { ref literal, .. }) => {
+            output.push_str(&String::from_utf8_lossy(literal))
+        }
+        NodeValue::CodeBlock(ref block) => {
+            output.push_str(&String::from_utf8_lossy(&block.literal))
+        }
+        NodeValue::HtmlBlock(ref block) => {
+            output.push_str(&TAG.replace_all(&String::from_utf8_lossy(&block.literal), " "))
+        }
+        NodeValue::HtmlInline(_) => {}
+        NodeValue::LineBreak | NodeValue::SoftBreak => output.push(' '),
+        _ => {
+            for child in node.children() {
+                collect_plain_text(child, output);
+            }
+        }
+    }
+    if value.block() {
+        output.push(' ');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Render `md` with the options of posts which matter here.
+    fn render_md(md: &str) -> Rendered {
+        let mut options = ComrakOptions::default();
+        options.parse.smart = true;
+        options.render.unsafe_ = true;
+        render(md, &options, &ComrakPlugins::default(), 0, false, false)
+    }
+
+    #[test]
+    fn punctuation_is_typographic_in_prose_only() {
+        let html = render_md(
+            "\"Quotes\", it's -- or --- so...\n\n`\"code\" -- it's...`\n\n```\n\"block\" -- it's...\n```\n",
+        )
+        .html;
+        assert_eq!(
+            html,
+            "<p>\u{201c}Quotes\u{201d}, it\u{2019}s \u{2013} or \u{2014} so\u{2026}</p>\n\
+             <p><code>&quot;code&quot; -- it's...</code></p>\n\
+             <pre><code>&quot;block&quot; -- it's...\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn anchors_keep_the_dashes_as_written() {
+        let rendered = render_md(
+            "# April 20 -- Edition planning\n\
+             # April 27 -- Type aliases and traits enforcement\n\
+             #### PGO-optimized LLVM -- Benchmark Results\n\
+             #### PGO-optimized Rust -- Benchmark Results\n\
+             ## Before---after\n",
+        );
+        let slugs: Vec<_> = rendered.toc.iter().map(|entry| &*entry.slug).collect();
+        assert_eq!(
+            slugs,
+            [
+                "april-20----edition-planning",
+                "april-27----type-aliases-and-traits-enforcement",
+                "pgo-optimized-llvm----benchmark-results",
+                "pgo-optimized-rust----benchmark-results",
+                "before---after",
+            ]
+        );
+        assert!(rendered
+            .html
+            .contains(r##"<a href="#april-20----edition-planning" aria-hidden="true" class="anchor" id="april-20----edition-planning"></a>"##));
+        // the table of contents shows the headings as rendered
+        assert_eq!(rendered.toc[0].text, "April 20 \u{2013} Edition planning");
+    }
+
+    #[test]
+    fn headings_get_anchors_and_a_table_of_contents() {
+        let rendered = render_md("# Intro\n## Details\n### More *details*\n# Intro\n## Intro\n");
+        let toc: Vec<_> = rendered
+            .toc
+            .iter()
+            .map(|entry| (entry.level, &*entry.text, &*entry.slug))
+            .collect();
+        assert_eq!(
+            toc,
+            [
+                (1, "Intro", "intro"),
+                (2, "Details", "details"),
+                (3, "More details", "more-details"),
+                (1, "Intro", "intro-1"),
+                (2, "Intro", "intro-2"),
+            ]
+        );
+        for slug in ["intro", "details", "more-details", "intro-1", "intro-2"] {
+            assert!(
+                rendered.html.contains(&format!("id=\"{}\"", slug)),
+                "{}",
+                rendered.html
+            );
+            assert!(
+                rendered.html.contains(&format!("href=\"#{}\"", slug)),
+                "{}",
+                rendered.html
+            );
+        }
+    }
+
+    #[test]
+    fn repeated_footnote_references_have_ids_of_their_own() {
+        let mut options = ComrakOptions::default();
+        options.extension.footnotes = true;
+        let md = "A[^1] B[^2] C[^1]\n\n[^1]: One\n[^2]: Two\n";
+        let html = render(md, &options, &ComrakPlugins::default(), 0, false, false).html;
+        assert!(html.starts_with(
+            "<p>A<sup class=\"footnote-ref\"><a href=\"#fn1\" id=\"fnref1\">1</a></sup> \
+             B<sup class=\"footnote-ref\"><a href=\"#fn2\" id=\"fnref2\">2</a></sup> \
+             C<sup class=\"footnote-ref\"><a href=\"#fn1\" id=\"fnref1-2\">1</a></sup></p>\n"
+        ));
+        // the footnotes point back at their first reference
+        assert!(html.contains(
+            "<li id=\"fn1\">\n<p>One <a href=\"#fnref1\" class=\"footnote-backref\">↩</a></p>"
+        ));
+        assert!(html.contains(
+            "<li id=\"fn2\">\n<p>Two <a href=\"#fnref2\" class=\"footnote-backref\">↩</a></p>"
+        ));
+    }
+
+    #[test]
+    fn alerts_are_admonitions() {
+        let html =
+            render_md("> [!NOTE]\n> Hi\n\n> [!WARNING]\n> Careful\n\n> [!NOPE]\n> Hm\n").html;
+        assert_eq!(
+            html,
+            "<div class=\"admonition admonition-note\">\n\
+             <p class=\"admonition-title\">Note</p>\n\
+             <p>Hi</p>\n\
+             </div>\n\
+             <div class=\"admonition admonition-warning\">\n\
+             <p class=\"admonition-title\">Warning</p>\n\
+             <p>Careful</p>\n\
+             </div>\n\
+             <blockquote>\n\
+             <p>[!NOPE]\nHm</p>\n\
+             </blockquote>\n"
+        );
+    }
+
+    /// The levels of the headings rendered from `md`, moved by `shift`.
+    fn heading_levels(md: &str, shift: u32) -> Vec<u32> {
+        let options = ComrakOptions::default();
+        let html = render(md, &options, &ComrakPlugins::default(), shift, false, false).html;
+        html.match_indices("<h")
+            .filter_map(|(i, _)| html[i + 2..i + 3].parse().ok())
+            .collect()
+    }
+
+    #[test]
+    fn headings_are_shifted_down() {
+        let md = "# One\n\n## Two\n\n### Three\n";
+        assert_eq!(heading_levels(md, 0), [1, 2, 3]);
+        assert_eq!(heading_levels(md, 1), [2, 3, 4]);
+        // the table of contents follows
+        let options = ComrakOptions::default();
+        let toc = render(md, &options, &ComrakPlugins::default(), 1, false, false).toc;
+        let levels: Vec<_> = toc.iter().map(|entry| entry.level).collect();
+        assert_eq!(levels, [2, 3, 4]);
+    }
+
+    #[test]
+    fn shifted_headings_stop_at_h6() {
+        let md = "# One\n\n#### Four\n\n###### Six\n";
+        assert_eq!(heading_levels(md, 3), [4, 6, 6]);
+        assert_eq!(heading_levels(md, 10), [6, 6, 6]);
+    }
+
+    #[test]
+    fn emoji_shortcodes_are_expanded_outside_of_code() {
+        assert_eq!(
+            render_md("Released :tada:\n").html,
+            "<p>Released \u{1f389}</p>\n"
+        );
+        assert_eq!(
+            render_md("Not an emoji :not_an_emoji: or 10:30:00\n").html,
+            "<p>Not an emoji :not_an_emoji: or 10:30:00</p>\n"
+        );
+        assert_eq!(
+            render_md("`:tada:`\n\n```\n:tada:\n```\n").html,
+            "<p><code>:tada:</code></p>\n<pre><code>:tada:\n</code></pre>\n"
+        );
+    }
+
+    /// Render `md` with math enabled.
+    fn render_math(md: &str) -> String {
+        let options = ComrakOptions::default();
+        render(md, &options, &ComrakPlugins::default(), 0, true, false).html
+    }
+
+    #[test]
+    fn inline_math_is_marked_up_for_katex() {
+        assert_eq!(
+            render_math("Inline $a*b*c < \\{x\\}$ math.\n"),
+            "<p>Inline <span class=\"math inline\">\\(a*b*c &lt; \\{x\\}\\)</span> math.</p>\n"
+        );
+        // prices aren't math
+        assert_eq!(render_math("From $5 or $6.\n"), "<p>From $5 or $6.</p>\n");
+    }
+
+    #[test]
+    fn display_math_replaces_its_paragraph() {
+        assert_eq!(
+            render_math("$$\n\\sum_i x_i\n$$\n\nAnd $$x$$ inline.\n"),
+            "<span class=\"math display\">\\[\\sum_i x_i\\]</span>\n\
+             <p>And <span class=\"math display\">\\[x\\]</span> inline.</p>\n"
+        );
+    }
+
+    #[test]
+    fn dollars_in_code_are_not_math() {
+        assert_eq!(
+            render_math("`$x$` and\n\n```\n$$y$$\n```\n\n    $z$\n"),
+            "<p><code>$x$</code> and</p>\n<pre><code>$$y$$\n</code></pre>\n<pre><code>$z$\n</code></pre>\n"
+        );
+        // nor anything without math enabled
+        assert_eq!(render_md("$x$\n").html, "<p>$x$</p>\n");
+    }
+}
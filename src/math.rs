@@ -0,0 +1,222 @@
+//! Math written as `$...$` or `$$...$$` in markdown.
+//!
+//! Math has to be taken out of the markdown before it is parsed, or the parser
+//! would turn `\{` into `{`, `a*b*c` into emphasis and so on. Each span is
+//! replaced by a placeholder, which is swapped for the markup KaTeX's
+//! auto-render extension picks up once the document is rendered.
+
+/// Placeholders are the index of the span between these private use characters,
+/// which markdown leaves alone.
+const START: char = '\u{E000}';
+const END: char = '\u{E001}';
+
+pub(crate) struct Math {
+    /// The TeX source of each span, and whether it is display math.
+    spans: Vec<(String, bool)>,
+}
+
+impl Math {
+    /// No math at all, for documents it isn't enabled for.
+    pub(crate) fn none() -> Math {
+        Math { spans: Vec::new() }
+    }
+
+    /// Replace the math in `md` with placeholders. Code blocks and code spans
+    /// are left alone, as are dollars which don't look like math delimiters,
+    /// like the ones in `$5 or $10`.
+    pub(crate) fn extract(md: &str) -> (String, Math) {
+        let mut math = Math::none();
+        let mut output = String::with_capacity(md.len());
+        let mut prose = String::new();
+        let mut fence: Option<String> = None;
+        let mut in_indented_code = false;
+        let mut previous_blank = true;
+
+        for line in md.split_inclusive('\n') {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            let is_blank = trimmed.is_empty();
+
+            if let Some(marker) = &fence {
+                if trimmed.starts_with(marker.as_str()) {
+                    fence = None;
+                }
+                output.push_str(line);
+            } else if indent <= 3 && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+                math.extract_prose(&prose, &mut output);
+                prose.clear();
+                let marker_char = trimmed.chars().next().unwrap();
+                let marker: String = trimmed.chars().take_while(|c| *c == marker_char).collect();
+                fence = Some(marker);
+                output.push_str(line);
+            } else if !is_blank && indent >= 4 && (previous_blank || in_indented_code) {
+                math.extract_prose(&prose, &mut output);
+                prose.clear();
+                in_indented_code = true;
+                output.push_str(line);
+            } else {
+                if !is_blank {
+                    in_indented_code = false;
+                }
+                prose.push_str(line);
+            }
+            previous_blank = is_blank;
+        }
+        math.extract_prose(&prose, &mut output);
+        (output, math)
+    }
+
+    fn extract_prose(&mut self, prose: &str, output: &mut String) {
+        let chars: Vec<char> = prose.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    output.push(chars[i]);
+                    output.push(chars[i + 1]);
+                    i += 2;
+                }
+                '`' => {
+                    let run = count(&chars[i..], '`');
+                    let end = find_backticks(&chars, i + run, run).unwrap_or(i + run);
+                    output.extend(&chars[i..end]);
+                    i = end;
+                }
+                '$' if chars.get(i + 1) == Some(&'$') => match find_display_end(&chars, i + 2) {
+                    Some(end) => {
+                        self.push(&chars[i + 2..end], true, output);
+                        i = end + 2;
+                    }
+                    None => {
+                        output.push_str("$$");
+                        i += 2;
+                    }
+                },
+                '$' => match find_inline_end(&chars, i + 1) {
+                    Some(end) => {
+                        self.push(&chars[i + 1..end], false, output);
+                        i = end + 1;
+                    }
+                    None => {
+                        output.push('$');
+                        i += 1;
+                    }
+                },
+                c => {
+                    output.push(c);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, tex: &[char], display: bool, output: &mut String) {
+        output.push(START);
+        output.push_str(&self.spans.len().to_string());
+        output.push(END);
+        self.spans.push((tex.iter().collect(), display));
+    }
+
+    /// Swap the placeholders in rendered html for KaTeX markup. Display math
+    /// alone in a paragraph replaces the paragraph.
+    pub(crate) fn restore_html(&self, html: &str) -> String {
+        if self.spans.is_empty() {
+            return html.to_string();
+        }
+        let mut html = html.to_string();
+        for (i, (tex, display)) in self.spans.iter().enumerate() {
+            let placeholder = format!("{}{}{}", START, i, END);
+            let tex = escape(tex.trim());
+            if *display {
+                let markup = format!(r#"<span class="math display">\[{}\]</span>"#, tex);
+                let paragraph = format!("<p>{}</p>", placeholder);
+                if html.contains(&paragraph) {
+                    html = html.replacen(&paragraph, &markup, 1);
+                } else {
+                    html = html.replacen(&placeholder, &markup, 1);
+                }
+            } else {
+                let markup = format!(r#"<span class="math inline">\({}\)</span>"#, tex);
+                html = html.replacen(&placeholder, &markup, 1);
+            }
+        }
+        html
+    }
+
+    /// Swap the placeholders in text for the TeX source they stand for.
+    pub(crate) fn restore_text(&self, text: &str) -> String {
+        if self.spans.is_empty() {
+            return text.to_string();
+        }
+        let mut text = text.to_string();
+        for (i, (tex, _)) in self.spans.iter().enumerate() {
+            text = text.replace(&format!("{}{}{}", START, i, END), tex.trim());
+        }
+        text
+    }
+}
+
+fn count(chars: &[char], c: char) -> usize {
+    chars.iter().take_while(|&&other| other == c).count()
+}
+
+/// The end of the code span closed by a run of exactly `run` backticks.
+fn find_backticks(chars: &[char], start: usize, run: usize) -> Option<usize> {
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i] == '`' {
+            let length = count(&chars[i..], '`');
+            if length == run {
+                return Some(i + length);
+            }
+            i += length;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+fn find_display_end(chars: &[char], start: usize) -> Option<usize> {
+    (start..chars.len().saturating_sub(1)).find(|&i| chars[i] == '$' && chars[i + 1] == '$')
+}
+
+/// Inline math, pandoc style: the opening `$` is followed by a non-space, the
+/// closing one preceded by a non-space and not followed by a digit, and there
+/// are no blank lines or code spans in between.
+fn find_inline_end(chars: &[char], start: usize) -> Option<usize> {
+    match chars.get(start) {
+        Some(c) if !c.is_whitespace() && *c != '$' => {}
+        _ => return None,
+    }
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            // math doesn't run into code spans
+            '`' => return None,
+            '\n' if chars[i + 1..]
+                .iter()
+                .take_while(|c| **c != '\n')
+                .all(|c| c.is_whitespace()) =>
+            {
+                return None
+            }
+            '$' if i > start
+                && !chars[i - 1].is_whitespace()
+                && !chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) =>
+            {
+                return Some(i)
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn escape(tex: &str) -> String {
+    tex.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -1,113 +1,590 @@
 use super::blogs::Manifest;
-use comrak::{ComrakExtensionOptions, ComrakOptions, ComrakRenderOptions};
+use super::config::Config;
+use super::markdown::{self, TocEntry};
+use super::vfs::FileSystem;
+use chrono::{Datelike, NaiveDate};
+use comrak::plugins::syntect::SyntectAdapter;
+use comrak::{
+    ComrakExtensionOptions, ComrakOptions, ComrakParseOptions, ComrakPlugins, ComrakRenderOptions,
+};
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::path::{Path, PathBuf};
+use std::fmt::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Reading speed used to estimate how long a post takes to read.
+static WORDS_PER_MINUTE: usize = 200;
+
+/// The directory of a blog with the files its posts can include, with a
+/// `{% include <name> %}` line.
+pub(crate) static INCLUDES_DIR: &str = "_includes";
+
+lazy_static::lazy_static! {
+    static ref INCLUDE: Regex = Regex::new(r"(?m)^\{%\s*include\s+(\S+?)\s*%\}[ \t]*$").unwrap();
+}
+
+/// Language of posts which don't specify one.
+pub(crate) static DEFAULT_LANG: &str = "en";
 
 #[derive(Debug, PartialEq, Deserialize)]
-struct YamlHeader {
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
     title: String,
-    author: String,
+    #[serde(default)]
+    authors: Authors,
     #[serde(default)]
     release: bool,
     team: Option<String>,
+    #[serde(default = "default_layout")]
     layout: String,
+    #[serde(default = "default_published")]
+    published: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+    slug: Option<String>,
+    updated: Option<String>,
+    description: Option<String>,
+    image: Option<String>,
+    #[serde(default)]
+    redirects: Vec<String>,
+    lang: Option<String>,
+    #[serde(default)]
+    translations: BTreeMap<String, String>,
+    canonical_url: Option<String>,
+    #[serde(default)]
+    featured: bool,
+    #[serde(default)]
+    noindex: bool,
+    order: Option<i64>,
+    series: Option<String>,
+    toc_max_depth: Option<u32>,
+    #[serde(default)]
+    allow_html: bool,
+}
+
+/// Old names of front matter fields, and the names they were renamed to. Posts
+/// using the old names keep working, see `Post::deprecated_fields`.
+static FIELD_ALIASES: &[(&str, &str)] = &[("author", "authors"), ("excerpt", "description")];
+
+/// The fields of front matter which use an old name, as the old and the new
+/// name. `separator` is what follows keys: `:` in YAML, `=` in TOML.
+fn renamed_fields(
+    path: &Path,
+    front_matter: &str,
+    separator: char,
+) -> Result<Vec<(String, String)>, Box<dyn Error + Send + Sync>> {
+    let keys: Vec<_> = front_matter
+        .lines()
+        .filter_map(|line| top_level_key(line, separator))
+        .collect();
+    let mut renamed = Vec::new();
+    for (old, new) in FIELD_ALIASES {
+        if !keys.contains(old) {
+            continue;
+        }
+        if keys.contains(new) {
+            return Err(PostError::new(
+                path,
+                format!(
+                    "has both `{}` and its new name `{}` in its front matter",
+                    old, new
+                ),
+            )
+            .into());
+        }
+        renamed.push((old.to_string(), new.to_string()));
+    }
+    Ok(renamed)
+}
+
+/// Give the `renamed` fields of front matter their new name. They are renamed
+/// in the source rather than once parsed, so errors still point at the right
+/// line.
+fn rename_fields(front_matter: &str, separator: char, renamed: &[(String, String)]) -> String {
+    front_matter
+        .split_inclusive('\n')
+        .map(|line| {
+            let key = top_level_key(line, separator);
+            match renamed.iter().find(|(old, _)| Some(old.as_str()) == key) {
+                Some((old, new)) => format!("{}{}", new, &line[old.len()..]),
+                None => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// The key of a line of front matter, for the fields of the top level, which
+/// start their line.
+fn top_level_key(line: &str, separator: char) -> Option<&str> {
+    let key = line[..line.find(separator)?].trim_end();
+    if key.is_empty() || line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(key)
+}
+
+/// The url of posts relative to their blog, unless the manifest sets another
+/// `permalink` pattern.
+pub(crate) static DEFAULT_PERMALINK: &str = ":year/:month/:day/:slug.html";
+
+/// The url of a post relative to its blog, from a permalink pattern like
+/// `:year/:slug/`. Urls ending with a `/` are directories, whose `index.html`
+/// is the post.
+pub(crate) fn permalink(
+    pattern: &str,
+    year: i32,
+    month: u32,
+    day: u32,
+    slug: &str,
+) -> Result<String, String> {
+    lazy_static::lazy_static! {
+        static ref TOKEN: Regex = Regex::new(r":([a-z]*)").unwrap();
+    }
+    let mut unknown = None;
+    let url = TOKEN.replace_all(pattern, |caps: &regex::Captures| match &caps[1] {
+        "year" => format!("{:04}", year),
+        "month" => format!("{:02}", month),
+        "day" => format!("{:02}", day),
+        "slug" => slug.to_string(),
+        _ => {
+            unknown.get_or_insert_with(|| caps[0].to_string());
+            String::new()
+        }
+    });
+    match unknown {
+        Some(token) => Err(format!(
+            "unknown token `{}` in permalink `{}`, expected `:year`, `:month`, `:day` or `:slug`",
+            token, pattern
+        )),
+        None => Ok(url.trim_start_matches('/').to_string()),
+    }
+}
+
+/// A regex matching every url a permalink pattern can give, with the
+/// `index.html` of directories like in the links resolved to files.
+pub(crate) fn permalink_regex(pattern: &str) -> Regex {
+    lazy_static::lazy_static! {
+        static ref TOKEN: Regex = Regex::new(r":([a-z]*)").unwrap();
+    }
+    let pattern = pattern.trim_start_matches('/');
+    let mut regex = String::from("^");
+    let mut end = 0;
+    for caps in TOKEN.captures_iter(pattern) {
+        let token = caps.get(0).unwrap();
+        regex.push_str(&regex::escape(&pattern[end..token.start()]));
+        regex.push_str(match &caps[1] {
+            "year" => r"\d{4}",
+            "month" | "day" => r"\d{2}",
+            _ => "[^/]+",
+        });
+        end = token.end();
+    }
+    regex.push_str(&regex::escape(&pattern[end..]));
+    if pattern.ends_with('/') {
+        regex.push_str(r"index\.html");
+    }
+    regex.push('$');
+    Regex::new(&regex).unwrap()
+}
+
+fn default_layout() -> String {
+    "post".into()
+}
+
+fn default_published() -> bool {
+    true
+}
+
+/// Posts can either be written by a single author or by a list of them.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Authors {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for Authors {
+    fn default() -> Self {
+        Authors::Many(Vec::new())
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl From<Authors> for Vec<String> {
+    fn from(authors: Authors) -> Self {
+        match authors {
+            Authors::One(author) => vec![author],
+            Authors::Many(authors) => authors,
+        }
+    }
+}
+
+/// The place of a post in its series, which is ordered by date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SeriesPart {
+    /// Starting at 1.
+    pub(crate) part: usize,
+    pub(crate) parts: usize,
+    pub(crate) prev: Option<PostLink>,
+    pub(crate) next: Option<PostLink>,
+}
+
+/// A link to another post of the same blog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PostLink {
+    pub(crate) title: String,
+    pub(crate) url: String,
+}
+
+/// What rendering the posts of a blog needs, set up once for all of them.
+/// It's shared between the threads rendering posts in parallel.
+pub(crate) struct RenderContext<'a> {
+    options: ComrakOptions,
+    highlighter: Option<SyntectAdapter<'a>>,
+    config: &'a Config,
+}
+
+impl<'a> RenderContext<'a> {
+    pub(crate) fn new(highlighter: Option<SyntectAdapter<'a>>, config: &'a Config) -> Self {
+        let options = ComrakOptions {
+            parse: ComrakParseOptions {
+                smart: true, // Typographic quotes, dashes and ellipses
+                ..ComrakParseOptions::default()
+            },
+            render: ComrakRenderOptions {
+                unsafe_: true, // Raw HTML is escaped beforehand, unless a post allows it
+                ..ComrakRenderOptions::default()
+            },
+            extension: ComrakExtensionOptions {
+                footnotes: true,
+                strikethrough: true,
+                table: true,
+                tasklist: true, // `- [ ]` and `- [x]` items as checkboxes
+                ..ComrakExtensionOptions::default()
+            },
+        };
+        RenderContext {
+            options,
+            highlighter,
+            config,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        self.config
+    }
+
+    fn render(&self, body: &str, manifest: &Manifest, allow_html: bool) -> markdown::Rendered {
+        let mut plugins = ComrakPlugins::default();
+        if let Some(highlighter) = &self.highlighter {
+            plugins.render.codefence_syntax_highlighter = Some(highlighter);
+        }
+        markdown::render(
+            body,
+            &self.options,
+            &plugins,
+            manifest.heading_shift,
+            manifest.math,
+            allow_html,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct Post {
+    #[serde(skip)]
+    pub(crate) path: PathBuf,
+    /// The source file of the post relative to the root of the repository,
+    /// which blogs are loaded from, with `/` as separator on every platform.
+    pub(crate) source_path: String,
+    /// Where the source of the post can be edited, if the config has an
+    /// `edit_url_base`.
+    pub(crate) edit_url: Option<String>,
     pub(crate) filename: String,
     pub(crate) layout: String,
     pub(crate) title: String,
     pub(crate) author: String,
+    pub(crate) authors: Vec<String>,
     pub(crate) year: i32,
     pub(crate) show_year: bool,
     pub(crate) month: u32,
     pub(crate) day: u32,
+    /// The date formatted as configured for the blog, if it has a format.
+    pub(crate) display_date: Option<String>,
     pub(crate) contents: String,
+    /// The contents as plain text.
+    text: String,
     pub(crate) url: String,
     pub(crate) published: String,
     pub(crate) updated: String,
+    pub(crate) updated_on: NaiveDate,
     pub(crate) release: bool,
+    pub(crate) draft: bool,
+    pub(crate) reading_time_minutes: usize,
+    word_count: usize,
+    heading_count: usize,
+    pub(crate) tags: Vec<String>,
+    pub(crate) toc: Vec<TocEntry>,
+    pub(crate) description: String,
+    pub(crate) image: Option<String>,
+    /// The image of previews of the post, like social media cards: its own
+    /// `image`, or else the `preview-image` of its blog.
+    pub(crate) preview_image: Option<String>,
+    /// Old paths of the post on the site, which should redirect to it.
+    pub(crate) redirects: Vec<String>,
+    pub(crate) lang: String,
+    /// The urls of translations of this post by language, as declared in its
+    /// front matter. See `Blog::translations` for all of them.
+    pub(crate) translations: BTreeMap<String, String>,
+    /// Where the post was originally published, if not on this site.
+    pub(crate) canonical_url: Option<String>,
+    /// Whether the post is pinned to the top of the index.
+    pub(crate) featured: bool,
+    /// Whether search engines are asked not to index the post. It's left out
+    /// of the sitemap too.
+    pub(crate) noindex: bool,
+    /// Where the post goes in blogs sorting their posts by `order`.
+    pub(crate) order: Option<i64>,
+    /// The name of the series of posts this one is part of.
+    pub(crate) series: Option<String>,
+    /// Where the post is in its series, see `Blog::arrange_posts`.
+    pub(crate) series_part: Option<SeriesPart>,
+    /// The post published before this one, see `Blog::arrange_posts`.
+    pub(crate) prev: Option<PostLink>,
+    /// The post published after this one.
+    pub(crate) next: Option<PostLink>,
+    /// The front matter fields the post uses an old name for, as the old and
+    /// the new name. See `FIELD_ALIASES`.
+    pub(crate) deprecated_fields: Vec<(String, String)>,
     pub(crate) has_team: bool,
     pub(crate) team: String,
     pub(crate) team_url: String,
 }
 
+/// Something wrong with a post, which says which file it is in.
+pub(crate) struct PostError {
+    path: PathBuf,
+    /// What is wrong, worded to follow the path, e.g. `has an unknown layout`.
+    message: String,
+}
+
+impl PostError {
+    fn new(path: &Path, message: impl Into<String>) -> Self {
+        PostError {
+            path: path.to_path_buf(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for PostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "blog post at path `{}` {}",
+            self.path.display(),
+            self.message
+        )
+    }
+}
+
+// Errors returned from `main` are printed with `Debug`.
+impl fmt::Debug for PostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for PostError {}
+
 impl Post {
-    pub(crate) fn open(path: &Path, manifest: &Manifest) -> Result<Self, Box<dyn Error>> {
-        // yeah this might blow up, but it won't
-        let filename = path.file_name().unwrap().to_str().unwrap();
+    pub(crate) fn open(
+        fs: &dyn FileSystem,
+        path: &Path,
+        manifest: &Manifest,
+        context: &RenderContext,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        // the name ends up in the url, so it can't just be converted lossily
+        let filename = match path.file_name().and_then(|name| name.to_str()) {
+            Some(filename) => filename,
+            None => {
+                return Err(
+                    PostError::new(path, "should have a file name which is valid UTF-8").into(),
+                )
+            }
+        };
 
         // we need to get the metadata out of the url
-        let mut split = filename.splitn(4, "-");
-
-        // we do some unwraps because these need to be valid
-        let year = split.next().unwrap().parse::<i32>().unwrap();
-        let month = split.next().unwrap().parse::<u32>().unwrap();
-        let day = split.next().unwrap().parse::<u32>().unwrap();
-        let filename = split.next().unwrap().to_string();
+        let (year, month, day, filename) = match split_filename(filename) {
+            Some(parts) => parts,
+            None => {
+                return Err(PostError::new(
+                    path,
+                    "should be named `YYYY-MM-DD-title`, with a valid date",
+                )
+                .into())
+            }
+        };
 
-        let contents = std::fs::read_to_string(path)?;
+        let contents = fs
+            .read_text(path)
+            .map_err(|e| PostError::new(path, format!("couldn't be read: {}", e)))?;
 
-        // yaml headers.... we know the first four bytes of each file are "---\n"
+        // front matter.... either yaml delimited by "---\n" or toml delimited by "+++\n".
+        // we know the first four bytes of each file are one of those delimiters
         // so we need to find the end. we need the fours to adjust for those first bytes
-        let end_of_yaml = contents[4..].find("---").unwrap() + 4;
-        let yaml = &contents[..end_of_yaml];
-        let YamlHeader {
-            author,
+        let (front_matter, deprecated_fields, end_of_front_matter) = if contents.starts_with("+++")
+        {
+            let end = closing_delimiter(path, &contents, "+++")?;
+            // keep the newline after the delimiter, for errors to count lines
+            // from the start of the file
+            let source = &contents[3..end];
+            let deprecated_fields = renamed_fields(path, source, '=')?;
+            let source = rename_fields(source, '=', &deprecated_fields);
+            let front_matter: FrontMatter = toml::from_str(&source).map_err(|e| {
+                PostError::new(path, format!("has invalid TOML front matter: {}", e))
+            })?;
+            (front_matter, deprecated_fields, end)
+        } else {
+            let end = closing_delimiter(path, &contents, "---")?;
+            let source = &contents[..end];
+            let deprecated_fields = renamed_fields(path, source, ':')?;
+            let source = rename_fields(source, ':', &deprecated_fields);
+            let front_matter: FrontMatter = serde_yaml::from_str(&source)
+                .map_err(|e| PostError::new(path, format!("has invalid front matter: {}", e)))?;
+            (front_matter, deprecated_fields, end)
+        };
+        let FrontMatter {
+            authors,
             title,
             release,
             team: team_string,
             layout,
-        } = serde_yaml::from_str(yaml)?;
-        // next, the contents. we add + to get rid of the final "---\n\n"
-        let options = ComrakOptions {
-            render: ComrakRenderOptions {
-                unsafe_: true, // Allow rendering of raw HTML
-                ..ComrakRenderOptions::default()
-            },
-            extension: ComrakExtensionOptions {
-                header_ids: Some(String::new()),
-                footnotes: true,
-                table: true,
-                ..ComrakExtensionOptions::default()
-            },
-            ..ComrakOptions::default()
+            published: is_published,
+            tags,
+            slug,
+            updated: updated_string,
+            description,
+            image,
+            redirects,
+            lang,
+            translations,
+            canonical_url,
+            featured,
+            noindex,
+            order,
+            series,
+            toc_max_depth,
+            allow_html,
+        } = front_matter;
+        // Content starts on the line after the closing delimiter
+        let body = contents.get(end_of_front_matter + 4..).ok_or_else(|| {
+            PostError::new(
+                path,
+                format!(
+                    "has truncated front matter: the closing `{}` should be followed by a newline",
+                    &contents[end_of_front_matter..end_of_front_matter + 3]
+                ),
+            )
+        })?;
+        let body = expand_includes(fs, path, body)?;
+        let word_count = body.split_whitespace().count();
+        let reading_time_minutes = word_count.div_ceil(WORDS_PER_MINUTE).max(1);
+        let rendered = context.render(&body, manifest, allow_html);
+        let contents = rendered.html;
+        let text = rendered.text;
+        let heading_count = rendered.toc.len();
+        // the depth is the level of headings as written, before they are moved down
+        let toc = match toc_max_depth.or(manifest.toc_max_depth) {
+            Some(depth) => rendered
+                .toc
+                .into_iter()
+                .filter(|entry| entry.level <= depth + manifest.heading_shift)
+                .collect(),
+            None => rendered.toc,
         };
 
-        // Content starts after "---\n" (we don't assume an extra newline)
-        let contents = comrak::markdown_to_html(&contents[end_of_yaml + 4..], &options);
+        // used when sharing the post, so don't leave it empty, even for posts
+        // without a paragraph of text
+        let description = description
+            .unwrap_or_else(|| excerpt(&rendered.first_paragraph, manifest.excerpt_length));
+        let description = if description.trim().is_empty() {
+            manifest.description.clone()
+        } else {
+            description
+        };
 
-        // finally, the url.
-        let mut url = PathBuf::from(&*filename);
-        url.set_extension("html");
+        // social media previews need absolute urls
+        let absolute = |image: String| {
+            if image.starts_with("http://") || image.starts_with("https://") {
+                image
+            } else {
+                format!(
+                    "{}{}",
+                    context.config.base_url,
+                    image.trim_start_matches('/')
+                )
+            }
+        };
+        let image = image.map(absolute);
+        let preview_image = image
+            .clone()
+            .or_else(|| manifest.preview_image.clone().map(absolute));
 
-        // this is fine
-        let url = format!(
-            "{:04}/{:02}/{:02}/{}",
-            year,
-            month,
-            day,
-            url.to_str().unwrap()
-        );
+        // finally, the url. the slug from the metadata wins over the file name.
+        let slug = match slug {
+            Some(slug) => slug,
+            None => Path::new(&*filename)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string(),
+        };
+        let url = permalink(&manifest.permalink, year, month, day, &slug)
+            .map_err(|e| PostError::new(path, format!("has an invalid url: {}", e)))?;
+
+        let authors: Vec<String> = authors.into();
+        let author = join_authors(&authors);
 
         let published = build_post_time(year, month, day, 0);
-        let updated = published.clone();
-
-        // validate for now that the layout is specified as "post"
-        match &*layout {
-            "post" => (),
-            _ => panic!(
-                "blog post at path `{}` should have layout `post`",
-                path.display()
+
+        // substantially revised posts can say when that happened, without
+        // moving them around in the index.
+        let updated_on = match updated_string {
+            Some(s) => match NaiveDate::parse_from_str(&s, "%Y-%m-%d") {
+                Ok(date) => date,
+                Err(_) => {
+                    return Err(PostError::new(
+                        path,
+                        "should have an `updated` date formatted as `YYYY-MM-DD`",
+                    )
+                    .into())
+                }
+            },
+            None => NaiveDate::from_ymd(year, month, day),
+        };
+        let updated = build_post_time(updated_on.year(), updated_on.month(), updated_on.day(), 0);
+
+        let display_date = match &manifest.date_format {
+            Some(format) => Some(
+                format_date(NaiveDate::from_ymd(year, month, day), format).map_err(|_| {
+                    PostError::new(
+                        path,
+                        format!(
+                            "has a date which the date-format `{}` can't display",
+                            format
+                        ),
+                    )
+                })?,
             ),
+            None => None,
         };
 
         // Enforce extra conditions
         if manifest.requires_team && team_string.is_none() {
-            panic!("blog post at path `{}` lacks team metadata", path.display());
+            return Err(PostError::new(path, "lacks team metadata").into());
         }
 
         // If they supplied team, it should look like `team-text <team-url>`
@@ -118,10 +595,13 @@ impl Post {
                 }
                 let captures = match R.captures(&s) {
                     Some(c) => c,
-                    None => panic!(
-                        "team from path `{}` should have format `$name <$url>`",
-                        path.display()
-                    ),
+                    None => {
+                        return Err(PostError::new(
+                            path,
+                            "should have a team formatted as `$name <$url>`",
+                        )
+                        .into())
+                    }
                 };
                 (
                     Some(captures["name"].to_string()),
@@ -132,19 +612,62 @@ impl Post {
             None => (None, None),
         };
 
+        let source_path = path
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let edit_url = context
+            .config
+            .edit_url_base
+            .as_ref()
+            .map(|base| format!("{}{}", base, source_path));
+
         Ok(Self {
+            path: path.to_path_buf(),
+            source_path,
+            edit_url,
             filename,
             title,
             author,
+            authors,
             year,
             show_year: false,
             month,
             day,
+            display_date,
             contents,
+            text,
             url,
             published,
             updated,
+            updated_on,
             release,
+            draft: !is_published,
+            reading_time_minutes,
+            word_count,
+            heading_count,
+            tags,
+            toc,
+            description,
+            image,
+            preview_image,
+            redirects,
+            lang: lang.unwrap_or_else(|| DEFAULT_LANG.to_string()),
+            translations: translations
+                .into_iter()
+                .map(|(lang, url)| (lang, url.trim_start_matches('/').to_string()))
+                .collect(),
+            canonical_url,
+            featured,
+            noindex,
+            order,
+            series,
+            series_part: None,
+            prev: None,
+            next: None,
+            deprecated_fields,
             layout,
             has_team: team.is_some(),
             team: team.unwrap_or_default(),
@@ -152,8 +675,142 @@ impl Post {
         })
     }
 
+    /// The number of words in the source of the post, as used to estimate the
+    /// reading time.
+    pub(crate) fn word_count(&self) -> usize {
+        self.word_count
+    }
+
+    pub(crate) fn heading_count(&self) -> usize {
+        self.heading_count
+    }
+
+    /// The contents without any markup, e.g. for indexing them.
+    pub(crate) fn plain_text(&self) -> &str {
+        &self.text
+    }
+
     pub fn set_updated(&mut self, seconds: u32) {
-        self.updated = build_post_time(self.year, self.month, self.day, seconds);
+        self.updated = build_post_time(
+            self.updated_on.year(),
+            self.updated_on.month(),
+            self.updated_on.day(),
+            seconds,
+        );
+    }
+}
+
+/// Replace the include lines of the body of the post at `path` with the file
+/// they name in the `INCLUDES_DIR` of its blog. Included files can't include
+/// others.
+fn expand_includes(fs: &dyn FileSystem, path: &Path, body: &str) -> Result<String, PostError> {
+    let mut expanded = String::with_capacity(body.len());
+    let mut last = 0;
+    for caps in INCLUDE.captures_iter(body) {
+        let name = &caps[1];
+        let file = include_path(path, name).ok_or_else(|| {
+            PostError::new(
+                path,
+                format!(
+                    "includes `{}`, which isn't a path below `{}`",
+                    name, INCLUDES_DIR
+                ),
+            )
+        })?;
+        let included = fs.read_text(&file).map_err(|e| {
+            PostError::new(
+                path,
+                format!(
+                    "includes `{}`, which couldn't be read: {}",
+                    file.display(),
+                    e
+                ),
+            )
+        })?;
+        let directive = caps.get(0).unwrap();
+        expanded.push_str(&body[last..directive.start()]);
+        expanded.push_str(included.trim_end_matches('\n'));
+        last = directive.end();
+    }
+    expanded.push_str(&body[last..]);
+    Ok(expanded)
+}
+
+/// The contents of the files the post at `path`, with the given source,
+/// includes. They are part of what it is built from. Missing ones are left
+/// out, as opening the post reports them.
+pub(crate) fn included_sources(fs: &dyn FileSystem, path: &Path, source: &str) -> String {
+    INCLUDE
+        .captures_iter(source)
+        .filter_map(|caps| include_path(path, &caps[1]))
+        .filter_map(|file| fs.read_text(&file).ok())
+        .collect()
+}
+
+/// The file an include of the post at `path` names, if it's a relative path
+/// which stays in the includes directory.
+fn include_path(path: &Path, name: &str) -> Option<PathBuf> {
+    let name = Path::new(name);
+    if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    Some(path.parent()?.join(INCLUDES_DIR).join(name))
+}
+
+/// Where the front matter of a post, which starts with `delimiter`, ends.
+fn closing_delimiter(path: &Path, contents: &str, delimiter: &str) -> Result<usize, PostError> {
+    match contents.get(4..).and_then(|rest| rest.find(delimiter)) {
+        Some(end) => Ok(end + 4),
+        None => Err(PostError::new(
+            path,
+            format!("has front matter without a closing `{}`", delimiter),
+        )),
+    }
+}
+
+/// Display `date` with a strftime-style `format`. Formats which can't be
+/// displayed for a date alone, like `%H:%M`, are an error rather than a panic.
+pub(crate) fn format_date(date: NaiveDate, format: &str) -> Result<String, fmt::Error> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", date.format(format))?;
+    Ok(formatted)
+}
+
+/// Split a post's file name into the date it was published and the rest of
+/// the name, making sure the date actually exists.
+fn split_filename(filename: &str) -> Option<(i32, u32, u32, String)> {
+    let mut split = filename.splitn(4, '-');
+    let year = split.next()?.parse::<i32>().ok()?;
+    let month = split.next()?.parse::<u32>().ok()?;
+    let day = split.next()?.parse::<u32>().ok()?;
+    let rest = split.next()?.to_string();
+    chrono::NaiveDate::from_ymd_opt(year, month, day)?;
+    Some((year, month, day, rest))
+}
+
+/// Shorten `text` to at most `limit` characters, cutting at a word boundary.
+fn excerpt(text: &str, limit: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= limit {
+        return text;
+    }
+    let end = text
+        .char_indices()
+        .nth(limit)
+        .map_or(text.len(), |(i, _)| i);
+    let cut = text[..end].rfind(' ').unwrap_or(end);
+    format!(
+        "{}…",
+        text[..cut].trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+/// Render a list of authors for display, e.g. "A, B and C".
+fn join_authors(authors: &[String]) -> String {
+    match authors {
+        [] => String::new(),
+        [author] => author.clone(),
+        [init @ .., last] => format!("{} and {}", init.join(", "), last),
     }
 }
 
@@ -164,3 +821,575 @@ fn build_post_time(year: i32, month: u32, day: u32, seconds: u32) -> String {
     )
     .to_rfc3339()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{self, parse_manifest, post};
+    use super::super::vfs::MemoryFs;
+    use super::*;
+
+    /// Open the post at `path` of `fs`, with `manifest` as the manifest of its
+    /// blog.
+    fn open(fs: &MemoryFs, path: &str, manifest: &str) -> Result<Post, String> {
+        open_with(fs, path, manifest, &Config::default())
+    }
+
+    fn open_with(
+        fs: &MemoryFs,
+        path: &str,
+        manifest: &str,
+        config: &Config,
+    ) -> Result<Post, String> {
+        let manifest = parse_manifest(manifest);
+        let context = RenderContext::new(None, config);
+        Post::open(fs, Path::new(path), &manifest, &context).map_err(|e| e.to_string())
+    }
+
+    /// Open a post with the given source in the test blog.
+    fn open_source(source: &str) -> Result<Post, String> {
+        let path = "posts/2020-03-04-a-post.md";
+        open(&MemoryFs::new([(path, source)]), path, testing::MANIFEST)
+    }
+
+    #[test]
+    fn opens_a_post_from_memory() {
+        let post = open_source(&post("A post", "authors: Ferris\n", "Hello *world*")).unwrap();
+        assert_eq!(post.title, "A post");
+        assert_eq!(post.author, "Ferris");
+        assert_eq!((post.year, post.month, post.day), (2020, 3, 4));
+        assert_eq!(post.url, "2020/03/04/a-post.html");
+        assert_eq!(post.contents, "<p>Hello <em>world</em></p>\n");
+        assert_eq!(post.source_path, "posts/2020-03-04-a-post.md");
+    }
+
+    #[test]
+    fn reports_a_post_which_cant_be_read() {
+        let err = open(
+            &MemoryFs::default(),
+            "posts/2020-03-04-gone.md",
+            testing::MANIFEST,
+        )
+        .unwrap_err();
+        assert!(
+            err.starts_with("blog post at path `posts/2020-03-04-gone.md` couldn't be read: "),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn images_are_made_absolute_with_the_base_url() {
+        let config = Config {
+            base_url: "https://example.com/staging/".into(),
+            ..Config::default()
+        };
+        let path = "posts/2020-03-04-a-post.md";
+        let image = |source: &str| {
+            open_with(
+                &MemoryFs::new([(path, source)]),
+                path,
+                testing::MANIFEST,
+                &config,
+            )
+            .unwrap()
+            .image
+        };
+        let local = post("A post", "image: /images/card.png\n", "");
+        assert_eq!(
+            image(&local).as_deref(),
+            Some("https://example.com/staging/images/card.png")
+        );
+        let remote = post("A post", "image: https://cdn.example/card.png\n", "");
+        assert_eq!(
+            image(&remote).as_deref(),
+            Some("https://cdn.example/card.png")
+        );
+    }
+
+    #[test]
+    fn links_to_where_the_source_can_be_edited() {
+        let path = "posts/inside-rust/2020-03-04-a-post.md";
+        let fs = MemoryFs::new([(path, &*post("A post", "", ""))]);
+        let config = Config {
+            edit_url_base: Some("https://github.com/rust-lang/blog/edit/master/".into()),
+            ..Config::default()
+        };
+        let post = open_with(&fs, path, testing::MANIFEST, &config).unwrap();
+        assert_eq!(
+            post.edit_url.as_deref(),
+            Some(
+                "https://github.com/rust-lang/blog/edit/master/posts/inside-rust/2020-03-04-a-post.md"
+            )
+        );
+        let post = open(&fs, path, testing::MANIFEST).unwrap();
+        assert_eq!(post.edit_url, None);
+    }
+
+    #[test]
+    fn errors_name_the_post() {
+        let cases = [
+            (
+                "---\ntitle: A post\n---",
+                "has truncated front matter: the closing `---` should be followed by a newline",
+            ),
+            (
+                "+++\ntitle = \"A post\"\n+++",
+                "has truncated front matter: the closing `+++` should be followed by a newline",
+            ),
+            (
+                "---\ntitle: A post\n",
+                "has front matter without a closing `---`",
+            ),
+            (
+                "---\ntitle: A post\nupdated: yesterday\n---\n",
+                "should have an `updated` date formatted as `YYYY-MM-DD`",
+            ),
+            (
+                "---\ntitle: A post\nteam: The Rust Team\n---\n",
+                "should have a team formatted as `$name <$url>`",
+            ),
+        ];
+        for (source, message) in cases {
+            let err = open_source(source).unwrap_err();
+            let expected = format!("blog post at path `posts/2020-03-04-a-post.md` {}", message);
+            assert!(err.starts_with(&expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn the_front_matter_can_end_the_file_with_a_newline() {
+        let post = open_source("---\ntitle: A post\n---\n").unwrap();
+        assert_eq!(post.contents, "");
+    }
+
+    #[test]
+    fn dates_are_displayed_with_the_date_format() {
+        let date = NaiveDate::from_ymd(2024, 1, 5);
+        assert_eq!(format_date(date, "%B %-d, %Y").unwrap(), "January 5, 2024");
+        assert_eq!(format_date(date, "%Y-%m-%d").unwrap(), "2024-01-05");
+        // a date has no time nor time zone
+        assert!(format_date(date, "%H:%M").is_err());
+        assert!(format_date(date, "%Z").is_err());
+        assert!(format_date(date, "%Q").is_err());
+
+        let path = "posts/2024-01-05-a-post.md";
+        let fs = MemoryFs::new([(path, &*post("A post", "", ""))]);
+        let manifest = testing::manifest("date-format: \"%B %-d, %Y\"\n");
+        let post = open(&fs, path, &manifest).unwrap();
+        assert_eq!(post.display_date.as_deref(), Some("January 5, 2024"));
+        // the machine dates stay the same
+        assert_eq!(post.published, "2024-01-05T00:00:00+00:00");
+        // without a format, the templates fall back to the default one
+        let post = open(&fs, path, testing::MANIFEST).unwrap();
+        assert_eq!(post.display_date, None);
+    }
+
+    #[test]
+    fn permalink_regexes_match_the_urls_of_their_pattern() {
+        let default = permalink_regex(DEFAULT_PERMALINK);
+        assert!(default.is_match("2020/01/02/a-post.html"));
+        assert!(!default.is_match("2020/1/02/a-post.html"));
+        assert!(!default.is_match("2020/01/02/a-post/index.html"));
+        assert!(!default.is_match("inside-rust/2020/01/02/a-post.html"));
+
+        let directories = permalink_regex("/:year/:slug/");
+        assert!(directories.is_match("2020/a-post/index.html"));
+        assert!(!directories.is_match("2020/a-post.html"));
+        assert!(!directories.is_match("2020/a/post/index.html"));
+    }
+
+    #[test]
+    fn front_matter_can_be_toml() {
+        let post =
+            open_source("+++\ntitle = \"A post\"\nauthors = [\"Ferris\", \"Bors\"]\n+++\nHello")
+                .unwrap();
+        assert_eq!(post.title, "A post");
+        assert_eq!(post.authors, ["Ferris", "Bors"]);
+        assert_eq!(post.contents, "<p>Hello</p>\n");
+
+        let err = open_source("+++\ntitle = \n+++\n").unwrap_err();
+        assert_eq!(
+            err,
+            "blog post at path `posts/2020-03-04-a-post.md` has invalid TOML front matter: \
+             expected a value, found a newline at line 2 column 9"
+        );
+    }
+
+    #[test]
+    fn file_names_start_with_a_real_date() {
+        let open_path = |path: &str| {
+            let fs = MemoryFs::new([(path, &*post("A post", "", ""))]);
+            open(&fs, path, testing::MANIFEST)
+        };
+        let post = open_path("posts/2024-02-29-leap-day.md").unwrap();
+        assert_eq!((post.year, post.month, post.day), (2024, 2, 29));
+        assert_eq!(post.url, "2024/02/29/leap-day.html");
+
+        for path in [
+            "posts/2024-13-01-a-post.md",
+            "posts/2023-02-29-a-post.md",
+            "posts/a-post.md",
+        ] {
+            assert_eq!(
+                open_path(path).unwrap_err(),
+                format!(
+                    "blog post at path `{}` should be named `YYYY-MM-DD-title`, with a valid date",
+                    path
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_front_matter_fields_are_pointed_at() {
+        let err = open_source("---\ntitle: A post\ntitel: A post\n---\n").unwrap_err();
+        assert!(
+            err.starts_with(
+                "blog post at path `posts/2020-03-04-a-post.md` has invalid front matter: \
+                 unknown field `titel`, expected one of `title`,"
+            ),
+            "{}",
+            err
+        );
+        // the line is the one in the file, below the opening `---`
+        assert!(err.ends_with("at line 3 column 1"), "{}", err);
+    }
+
+    #[test]
+    fn reading_times_are_rounded_up_to_whole_minutes() {
+        let minutes = |words: usize| {
+            let post = open_source(&post("A post", "", &"word ".repeat(words))).unwrap();
+            assert_eq!(post.word_count(), words);
+            post.reading_time_minutes
+        };
+        assert_eq!(minutes(0), 1);
+        assert_eq!(minutes(200), 1);
+        assert_eq!(minutes(201), 2);
+        assert_eq!(minutes(450), 3);
+
+        let post = open_source(&post("A post", "", "Hello")).unwrap();
+        let post = serde_json::to_value(&post).unwrap();
+        assert_eq!(post["reading_time_minutes"], 1);
+    }
+
+    #[test]
+    fn posts_can_have_several_authors() {
+        let authors = |front_matter: &str| {
+            let post = open_source(&post("A post", front_matter, "")).unwrap();
+            (post.authors, post.author)
+        };
+        assert_eq!(
+            authors("authors: Ferris\n"),
+            (vec!["Ferris".into()], "Ferris".into())
+        );
+        assert_eq!(
+            authors("authors: [Ferris, Bors, Crab]\n"),
+            (
+                vec!["Ferris".into(), "Bors".into(), "Crab".into()],
+                "Ferris, Bors and Crab".into()
+            )
+        );
+        assert_eq!(authors("authors: []\n"), (vec![], String::new()));
+        assert_eq!(authors(""), (vec![], String::new()));
+        // under its old name too
+        assert_eq!(
+            authors("author: Ferris\n"),
+            (vec!["Ferris".into()], "Ferris".into())
+        );
+
+        let post = open_source(&post("A post", "authors: [Ferris, Bors]\n", "")).unwrap();
+        let post = serde_json::to_value(&post).unwrap();
+        assert_eq!(post["authors"], serde_json::json!(["Ferris", "Bors"]));
+    }
+
+    #[test]
+    fn posts_are_updated_when_published_unless_told_otherwise() {
+        let post = open_source(&post("A post", "", "")).unwrap();
+        assert_eq!(post.published, "2020-03-04T00:00:00+00:00");
+        assert_eq!(post.updated, post.published);
+        assert_eq!(post.updated_on, NaiveDate::from_ymd(2020, 3, 4));
+
+        let revised = open_source(&testing::post("A post", "updated: 2021-05-06\n", "")).unwrap();
+        assert_eq!(revised.published, "2020-03-04T00:00:00+00:00");
+        assert_eq!(revised.updated, "2021-05-06T00:00:00+00:00");
+        let revised = serde_json::to_value(&revised).unwrap();
+        assert_eq!(revised["updated"], "2021-05-06T00:00:00+00:00");
+    }
+
+    #[test]
+    fn descriptions_are_declared_or_taken_from_the_first_paragraph() {
+        let description = |front_matter: &str, body: &str, manifest: &str| {
+            let path = "posts/2020-03-04-a-post.md";
+            let fs = MemoryFs::new([(path, &*post("A post", front_matter, body))]);
+            open(&fs, path, manifest).unwrap().description
+        };
+        let body = "Some *words* to read, and then some more.\n\nThe second paragraph.";
+        assert_eq!(
+            description("description: Declared\n", body, testing::MANIFEST),
+            "Declared"
+        );
+        assert_eq!(
+            description("excerpt: Declared\n", body, testing::MANIFEST),
+            "Declared"
+        );
+        assert_eq!(
+            description("", body, testing::MANIFEST),
+            "Some words to read, and then some more."
+        );
+        // cut at the last word which fits, without its punctuation
+        let short = testing::manifest("excerpt-length: 25\n");
+        assert_eq!(description("", body, &short), "Some words to read, and…");
+        assert_eq!(description("", "Short.", &short), "Short.");
+        assert_eq!(excerpt("Ünïcödé wörds", 8), "Ünïcödé…");
+    }
+
+    #[test]
+    fn posts_have_a_plain_text_rendering() {
+        let post = open_source(&post(
+            "A post",
+            "allow_html: true\n",
+            "# Getting   started\n\n\
+             Read [the *book*](https://doc.rust-lang.org/book/) first.<br>\n\
+             Then run `cargo new`:\n\n\
+             ```rust\nfn main() {}\n```\n\n\
+             <div class=\"note\">A <b>note</b></div>\n\n\
+             - one\n- two\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            post.plain_text(),
+            "Getting started Read the book first. Then run cargo new: fn main() {} A note one two"
+        );
+    }
+
+    #[test]
+    fn posts_count_their_words_and_headings() {
+        let post = open_source(&post(
+            "A post",
+            "",
+            "# One heading\n\nSome words here.\n\n## Another heading\n\n### A third\n\nThe end\n",
+        ))
+        .unwrap();
+        // the words of the source, like the reading time, which counts the
+        // `#` of headings too
+        assert_eq!(post.word_count(), 14);
+        assert_eq!(post.heading_count(), 3);
+
+        let post = serde_json::to_value(&post).unwrap();
+        assert_eq!(post["word_count"], 14);
+        assert_eq!(post["heading_count"], 3);
+    }
+
+    #[test]
+    fn posts_without_text_are_described_like_their_blog() {
+        let description = |front_matter: &str, body: &str| {
+            open_source(&post("A post", front_matter, body))
+                .unwrap()
+                .description
+        };
+        let body = "# Just a heading\n\n```\nand code\n```\n";
+        assert_eq!(description("description: Declared\n", body), "Declared");
+        assert_eq!(
+            description("", &format!("{}\nThen some text.\n", body)),
+            "Then some text."
+        );
+        assert_eq!(description("", body), "Posts for the tests");
+        assert_eq!(description("", ""), "Posts for the tests");
+    }
+
+    #[test]
+    fn old_field_names_still_work() {
+        let post = open_source(&testing::post(
+            "A post",
+            "author: Ferris\nexcerpt: About it\n",
+            "",
+        ))
+        .unwrap();
+        assert_eq!(post.authors, ["Ferris"]);
+        assert_eq!(post.description, "About it");
+        assert_eq!(
+            post.deprecated_fields,
+            [
+                ("author".to_string(), "authors".to_string()),
+                ("excerpt".to_string(), "description".to_string()),
+            ]
+        );
+        let post = open_source("+++\ntitle = \"A post\"\nauthor = \"Ferris\"\n+++\n").unwrap();
+        assert_eq!(post.authors, ["Ferris"]);
+        assert_eq!(post.deprecated_fields.len(), 1);
+
+        // only at the top level, and not with the new name too
+        let post = open_source(&testing::post("A post", "authors: Ferris\n", "")).unwrap();
+        assert!(post.deprecated_fields.is_empty());
+        let err = open_source(&testing::post(
+            "A post",
+            "author: Ferris\nauthors: Bors\n",
+            "",
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "blog post at path `posts/2020-03-04-a-post.md` has both `author` and its new name `authors` in its front matter"
+        );
+    }
+
+    #[test]
+    fn the_table_of_contents_can_stop_at_a_depth() {
+        let body = "# One\n\n## Two\n\n### Three\n\n#### Four\n";
+        let toc = |front_matter: &str, manifest: &str| -> Vec<String> {
+            let path = "posts/2020-03-04-a-post.md";
+            let fs = MemoryFs::new([(path, &*post("A post", front_matter, body))]);
+            let post = open(&fs, path, manifest).unwrap();
+            // every heading keeps its anchor
+            for slug in ["one", "two", "three", "four"] {
+                assert!(post.contents.contains(&format!("id=\"{}\"", slug)));
+            }
+            post.toc.iter().map(|entry| entry.slug.clone()).collect()
+        };
+        assert_eq!(toc("", testing::MANIFEST), ["one", "two", "three", "four"]);
+        assert_eq!(toc("toc_max_depth: 2\n", testing::MANIFEST), ["one", "two"]);
+        let shallow = testing::manifest("toc-max-depth: 3\n");
+        assert_eq!(toc("", &shallow), ["one", "two", "three"]);
+        assert_eq!(toc("toc_max_depth: 2\n", &shallow), ["one", "two"]);
+        // the depth counts the headings as written, even when they are shifted
+        let shifted = testing::manifest("heading-shift: 1\n");
+        assert_eq!(toc("toc_max_depth: 2\n", &shifted), ["one", "two"]);
+    }
+
+    #[test]
+    fn task_lists_are_rendered_as_checkboxes() {
+        let post = open_source(&post(
+            "A post",
+            "",
+            "- [ ] todo\n- [x] done\n- [X] also done\n- plain\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            post.contents
+                .matches("<input type=\"checkbox\" disabled=\"\"")
+                .count(),
+            3
+        );
+        assert_eq!(post.contents.matches("checked=\"\"").count(), 2);
+        assert_eq!(
+            post.contents,
+            "<ul>\n\
+             <li><input type=\"checkbox\" disabled=\"\" /> todo</li>\n\
+             <li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> done</li>\n\
+             <li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> also done</li>\n\
+             <li>plain</li>\n\
+             </ul>\n"
+        );
+    }
+
+    #[test]
+    fn strikethrough_and_tables_are_rendered() {
+        let post = open_source(&post(
+            "A post",
+            "",
+            "~~gone~~ and ~one~\n\n| A | B |\n|---|:-:|\n| 1 | 2 |\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            post.contents,
+            "<p><del>gone</del> and <del>one</del></p>\n\
+             <table>\n<thead>\n<tr>\n<th>A</th>\n<th align=\"center\">B</th>\n</tr>\n</thead>\n\
+             <tbody>\n<tr>\n<td>1</td>\n<td align=\"center\">2</td>\n</tr>\n</tbody>\n\
+             </table>\n"
+        );
+    }
+
+    #[test]
+    fn raw_html_is_escaped_unless_allowed() {
+        let contents = "<div class=\"w\">x</div>\n\nInline <b>bold</b> <script>alert(1)</script>\n";
+        let escaped = open_source(&post("A post", "", contents)).unwrap();
+        assert_eq!(
+            escaped.contents,
+            "<p>&lt;div class=&quot;w&quot;&gt;x&lt;/div&gt;</p>\n\
+             <p>Inline &lt;b&gt;bold&lt;/b&gt; &lt;script&gt;alert(1)&lt;/script&gt;</p>\n"
+        );
+
+        let allowed = open_source(&post("A post", "allow_html: true\n", contents)).unwrap();
+        assert_eq!(
+            allowed.contents,
+            "<div class=\"w\">x</div>\n<p>Inline <b>bold</b> <script>alert(1)</script></p>\n"
+        );
+    }
+
+    #[test]
+    fn include_lines_are_replaced_by_the_file_they_name() {
+        let path = "posts/2020-03-04-a-post.md";
+        let source = post(
+            "A post",
+            "",
+            "Hello\n\n{% include disclaimer.md %}\n\n{%include notes/more.md%}\n",
+        );
+        let fs = MemoryFs::new([
+            (path, &*source),
+            ("posts/_includes/disclaimer.md", "*Not* advice.\n"),
+            ("posts/_includes/notes/more.md", "More"),
+        ]);
+        let expanded = open(&fs, path, testing::MANIFEST).unwrap();
+        assert_eq!(
+            expanded.contents,
+            "<p>Hello</p>\n<p><em>Not</em> advice.</p>\n<p>More</p>\n"
+        );
+
+        // they have to be on a line of their own
+        let inline = open_source(&post("A post", "", "See {% include disclaimer.md %}")).unwrap();
+        assert_eq!(inline.contents, "<p>See {% include disclaimer.md %}</p>\n");
+    }
+
+    #[test]
+    fn includes_which_cant_be_read_are_reported() {
+        let err = open_source(&post("A post", "", "{% include gone.md %}\n")).unwrap_err();
+        assert!(
+            err.starts_with(
+                "blog post at path `posts/2020-03-04-a-post.md` includes \
+                 `posts/_includes/gone.md`, which couldn't be read: "
+            ),
+            "{}",
+            err
+        );
+
+        let err = open_source(&post("A post", "", "{% include ../blog.yml %}\n")).unwrap_err();
+        assert_eq!(
+            err,
+            "blog post at path `posts/2020-03-04-a-post.md` includes `../blog.yml`, \
+             which isn't a path below `_includes`"
+        );
+    }
+
+    #[test]
+    fn posts_without_an_image_are_previewed_with_the_one_of_their_blog() {
+        let path = "posts/2020-03-04-a-post.md";
+        let images = |manifest: &str, source: &str| {
+            let post = open(&MemoryFs::new([(path, source)]), path, manifest).unwrap();
+            (post.image, post.preview_image)
+        };
+        let with_preview = testing::manifest("preview-image: /images/blog-card.png\n");
+        let base_url = Config::default().base_url;
+
+        let own = post("A post", "image: /images/card.png\n", "");
+        let card = format!("{}images/card.png", base_url);
+        assert_eq!(
+            images(&with_preview, &own),
+            (Some(card.clone()), Some(card))
+        );
+
+        let without = post("A post", "", "");
+        assert_eq!(
+            images(&with_preview, &without),
+            (None, Some(format!("{}images/blog-card.png", base_url)))
+        );
+        assert_eq!(images(testing::MANIFEST, &without), (None, None));
+    }
+
+    #[test]
+    fn source_paths_leave_out_the_current_directory() {
+        let path = "./posts/inside-rust/2020-03-04-a-post.md";
+        let fs = MemoryFs::new([(path, &*post("A post", "", ""))]);
+        let post = open(&fs, path, testing::MANIFEST).unwrap();
+        assert_eq!(post.source_path, "posts/inside-rust/2020-03-04-a-post.md");
+    }
+}
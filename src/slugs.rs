@@ -0,0 +1,95 @@
+//! Slugs: the url-friendly forms of titles, names and headings.
+
+use regex::Regex;
+use std::collections::HashSet;
+
+/// Turn `text` into a slug, the same way heading anchors have always been
+/// derived on the site, so links to them keep working:
+///
+/// 1. the text is lowercased,
+/// 2. everything but letters, marks, numbers, connector punctuation like `_`,
+///    hyphens and spaces is removed,
+/// 3. each space becomes a hyphen.
+///
+/// Runs of spaces aren't collapsed, as the anchors of existing headings like
+/// ``The `?` operator`` (`the--operator`) depend on it. Hyphens already in the
+/// text are kept, so a slug is left as it is. Headings are slugified as
+/// written, before smart punctuation turns their `--` into `–`.
+pub(crate) fn slugify(text: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref REJECTED: Regex = Regex::new(r"[^\p{L}\p{M}\p{N}\p{Pc} -]").unwrap();
+    }
+    REJECTED
+        .replace_all(&text.to_lowercase(), "")
+        .replace(' ', "-")
+}
+
+/// Hands out unique slugs within a document, suffixing repeated ones with
+/// `-1`, `-2` and so on.
+#[derive(Default)]
+pub(crate) struct Slugs(HashSet<String>);
+
+impl Slugs {
+    pub(crate) fn unique(&mut self, text: &str) -> String {
+        let slug = slugify(text);
+        let mut unique = slug.clone();
+        let mut suffix = 0;
+        while self.0.contains(&unique) {
+            suffix += 1;
+            unique = format!("{}-{}", slug, suffix);
+        }
+        self.0.insert(unique.clone());
+        unique
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugify_matches_the_historical_anchors() {
+        let cases = [
+            ("Hello World", "hello-world"),
+            ("Rust 1.0: Stable!", "rust-10-stable"),
+            ("The `?` operator", "the--operator"),
+            ("Two  spaces", "two--spaces"),
+            ("Trailing punctuation...", "trailing-punctuation"),
+            ("snake_case stays", "snake_case-stays"),
+            ("Ünïcödé Straße", "ünïcödé-straße"),
+            ("日本語のタイトル", "日本語のタイトル"),
+            ("Crabs 🦀 everywhere", "crabs--everywhere"),
+            ("already-a-slug", "already-a-slug"),
+            // published anchors, which must not change
+            (
+                "April 20 -- Edition planning",
+                "april-20----edition-planning",
+            ),
+            (
+                "April 27 -- Type aliases and traits enforcement",
+                "april-27----type-aliases-and-traits-enforcement",
+            ),
+            (
+                "PGO-optimized LLVM -- Benchmark Results",
+                "pgo-optimized-llvm----benchmark-results",
+            ),
+            (
+                "PGO-optimized Rust -- Benchmark Results",
+                "pgo-optimized-rust----benchmark-results",
+            ),
+        ];
+        for (text, slug) in cases {
+            assert_eq!(slugify(text), slug, "slug of {:?}", text);
+            assert_eq!(slugify(slug), slug, "slug of the slug of {:?}", text);
+        }
+    }
+
+    #[test]
+    fn repeated_slugs_are_suffixed() {
+        let mut slugs = Slugs::default();
+        assert_eq!(slugs.unique("Usage"), "usage");
+        assert_eq!(slugs.unique("usage"), "usage-1");
+        assert_eq!(slugs.unique("Usage!"), "usage-2");
+        assert_eq!(slugs.unique("Other"), "other");
+    }
+}
@@ -0,0 +1,100 @@
+//! What the tests of the other modules share: blogs small enough to write out
+//! in full, loaded from memory.
+
+use super::blogs::{Blog, Manifest};
+use super::cache::PostCache;
+use super::config::Config;
+use super::vfs::MemoryFs;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The fields every manifest needs.
+pub(crate) static MANIFEST: &str = "\
+title: Test Blog
+index-title: The Test Blog
+description: Posts for the tests
+maintained-by: the test team
+index-html: <p>Hello</p>
+requires-team: false
+link-text: the test blog
+";
+
+/// `MANIFEST` with the `extra` lines of YAML added to it.
+pub(crate) fn manifest(extra: &str) -> String {
+    format!("{}{}", MANIFEST, extra)
+}
+
+/// A post with YAML front matter, made of the `title` and the `extra` lines.
+pub(crate) fn post(title: &str, extra: &str, body: &str) -> String {
+    format!("---\ntitle: {}\n{}---\n{}", title, extra, body)
+}
+
+/// Parse `manifest` as a manifest.
+pub(crate) fn parse_manifest(manifest: &str) -> Manifest {
+    serde_yaml::from_str(manifest).unwrap()
+}
+
+/// Load the blog in the `posts` directory of the files, with a fresh cache.
+pub(crate) fn load_blog(fs: &MemoryFs) -> Result<Blog, Box<dyn Error + Send + Sync>> {
+    load_blog_with(fs, &Config::default())
+}
+
+/// `load_blog`, built with the given config.
+pub(crate) fn load_blog_with(
+    fs: &MemoryFs,
+    config: &Config,
+) -> Result<Blog, Box<dyn Error + Send + Sync>> {
+    Blog::load_from(
+        fs,
+        PathBuf::new(),
+        Path::new("posts"),
+        &PostCache::default(),
+        config,
+    )
+}
+
+/// A directory of its own for a test on disk, removed with everything in it
+/// once dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "blog-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Write the file at `path`, relative to the directory, along with the
+    /// directories it's in.
+    pub(crate) fn write(&self, path: &str, contents: impl AsRef<[u8]>) {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    pub(crate) fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.0.join(path)).unwrap()
+    }
+
+    pub(crate) fn exists(&self, path: &str) -> bool {
+        self.0.join(path).exists()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,210 @@
+//! The filesystem blogs are loaded from. Loading goes through the
+//! [`FileSystem`] trait rather than `std::fs`, so blogs can be loaded from
+//! somewhere else than the disk too, like an in-memory tree of files.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub(crate) trait FileSystem: Sync {
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+
+    /// Read a text file written by hand, without the byte order mark and the
+    /// `\r\n` line endings some Windows editors write.
+    fn read_text(&self, path: &Path) -> io::Result<String> {
+        let contents = self.read_to_string(path)?;
+        let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+        Ok(contents.replace("\r\n", "\n"))
+    }
+
+    /// The paths of the entries of the directory at `path`.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// A path identifying the file at `path`, whichever way it is reached.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+}
+
+/// What loading blogs needs to know about a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Metadata {
+    File,
+    Dir,
+    /// Anything else, e.g. a socket.
+    Other,
+}
+
+impl Metadata {
+    pub(crate) fn is_file(self) -> bool {
+        self == Metadata::File
+    }
+
+    pub(crate) fn is_dir(self) -> bool {
+        self == Metadata::Dir
+    }
+}
+
+/// The actual filesystem, with symlinks followed.
+pub(crate) struct Disk;
+
+impl FileSystem for Disk {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let file_type = path.metadata()?.file_type();
+        Ok(if file_type.is_file() {
+            Metadata::File
+        } else if file_type.is_dir() {
+            Metadata::Dir
+        } else {
+            Metadata::Other
+        })
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+}
+
+/// Files kept in memory, for tests. The directories are the ancestors of the
+/// files, so there are no empty ones.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryFs {
+    files: std::collections::BTreeMap<PathBuf, String>,
+}
+
+#[cfg(test)]
+impl MemoryFs {
+    /// A filesystem with the given files, as `(path, contents)` pairs.
+    pub(crate) fn new<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut fs = MemoryFs::default();
+        for (path, contents) in files {
+            fs.insert(path, contents);
+        }
+        fs
+    }
+
+    pub(crate) fn insert(&mut self, path: impl Into<PathBuf>, contents: impl Into<String>) {
+        self.files.insert(path.into(), contents.into());
+    }
+
+    pub(crate) fn remove(&mut self, path: impl AsRef<Path>) {
+        self.files.remove(path.as_ref());
+    }
+}
+
+#[cfg(test)]
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no file at `{}`", path.display()),
+    )
+}
+
+#[cfg(test)]
+impl FileSystem for MemoryFs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.files.get(path).cloned().ok_or_else(|| not_found(path))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        if !self.metadata(path)?.is_dir() {
+            return Err(io::Error::other(format!(
+                "`{}` isn't a directory",
+                path.display()
+            )));
+        }
+        let entries: std::collections::BTreeSet<_> = self
+            .files
+            .keys()
+            .filter_map(|file| file.strip_prefix(path).ok()?.components().next())
+            .map(|entry| path.join(entry))
+            .collect();
+        Ok(entries.into_iter().collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        if self.files.contains_key(path) {
+            Ok(Metadata::File)
+        } else if self.files.keys().any(|file| file.starts_with(path)) {
+            Ok(Metadata::Dir)
+        } else {
+            Err(not_found(path))
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.metadata(path)?;
+        Ok(path.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fs() -> MemoryFs {
+        MemoryFs::new([
+            ("posts/blog.yml", "title: Blog"),
+            ("posts/2020-01-01-post.md", "---\r\ntitle: Post\r\n"),
+            ("posts/inside/blog.yml", "title: Inside"),
+        ])
+    }
+
+    #[test]
+    fn directories_are_the_ancestors_of_files() {
+        let fs = fs();
+        assert_eq!(fs.metadata(Path::new("posts")).unwrap(), Metadata::Dir);
+        assert_eq!(
+            fs.metadata(Path::new("posts/inside")).unwrap(),
+            Metadata::Dir
+        );
+        assert_eq!(
+            fs.metadata(Path::new("posts/blog.yml")).unwrap(),
+            Metadata::File
+        );
+        let missing = fs.metadata(Path::new("posts/missing")).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        assert!(!fs.exists(Path::new("post")));
+    }
+
+    #[test]
+    fn read_dir_lists_direct_children_once() {
+        let entries = fs().read_dir(Path::new("posts")).unwrap();
+        assert_eq!(
+            entries,
+            [
+                PathBuf::from("posts/2020-01-01-post.md"),
+                PathBuf::from("posts/blog.yml"),
+                PathBuf::from("posts/inside"),
+            ]
+        );
+        assert!(fs().read_dir(Path::new("posts/blog.yml")).is_err());
+    }
+
+    #[test]
+    fn read_text_normalizes_line_endings() {
+        let mut fs = fs();
+        fs.insert("bom.md", "\u{feff}bom");
+        assert_eq!(
+            fs.read_text(Path::new("posts/2020-01-01-post.md")).unwrap(),
+            "---\ntitle: Post\n"
+        );
+        assert_eq!(fs.read_text(Path::new("bom.md")).unwrap(), "bom");
+        fs.remove("bom.md");
+        assert!(fs.read_text(Path::new("bom.md")).is_err());
+    }
+}